@@ -0,0 +1,214 @@
+use std::path::PathBuf;
+
+use clap::{error::ErrorKind, CommandFactory, Parser, Subcommand};
+
+use sprint::{error::SprintError, sprint_config::{self, SearchConfig, SprintConfig}, wayland::{ConfigReload, LaunchOptions, SelectAction}};
+
+#[derive(Clone, Debug, Parser)]
+#[command(version, about)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    /// The config file to use instead of the default one
+    #[arg(long, global = true)]
+    pub config: Option<PathBuf>,
+    /// Text to pre-fill the search box with
+    #[arg(long)]
+    pub query: Option<String>,
+    /// The result types to show and their order, overriding the config's `result_order`, e.g. desktop,math
+    #[arg(long, value_delimiter = ',', global = true)]
+    pub providers: Option<Vec<String>>,
+    /// The text shown in the search box before anything is typed
+    #[arg(long)]
+    pub placeholder: Option<String>,
+    /// The width of the window, in logical pixels
+    #[arg(long, value_parser = clap::value_parser!(u32).range(100..))]
+    pub width: Option<u32>,
+    /// The height of the window, in logical pixels
+    #[arg(long, value_parser = clap::value_parser!(u32).range(100..))]
+    pub height: Option<u32>,
+    /// The output to open on, overriding the config. One of focused, primary, or an output name like DP-1
+    #[arg(long)]
+    pub output: Option<String>,
+    /// Act like dmenu, picking from newline separated items on stdin and printing the pick to stdout
+    #[arg(long, conflicts_with = "daemon")]
+    pub dmenu: bool,
+    /// With --dmenu, print what was typed rather than the picked item
+    #[arg(long, requires = "dmenu")]
+    pub print_query: bool,
+    /// Print the kind, label and target of the picked result as a tab separated line instead of launching it
+    #[arg(long, conflicts_with_all = ["daemon", "print_query", "json"])]
+    pub print: bool,
+    /// Print a JSON description of the picked result instead of launching it
+    #[arg(long, conflicts_with_all = ["daemon", "print_query"])]
+    pub json: bool,
+    /// Print the default config file and exit
+    #[arg(long)]
+    pub print_default_config: bool,
+    /// Check the config for problems, printing them with where they are, and exit
+    #[arg(long)]
+    pub check_config: bool,
+    /// Run in the background, ready to be shown instantly with `sprint` or `sprint --toggle`
+    #[arg(long, conflicts_with = "toggle")]
+    pub daemon: bool,
+    /// Show or hide the running daemon, failing if there isn't one
+    #[arg(long)]
+    pub toggle: bool,
+    /// Log more, once for info, twice for debug including timings, three times for everything. `SPRINT_LOG` overrides this
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    pub verbose: u8,
+    /// Also write the log to `$XDG_STATE_HOME/sprint/sprint.log`
+    #[arg(long, global = true)]
+    pub log_file: bool,
+}
+#[derive(Clone, Debug, Subcommand)]
+pub enum Command {
+    /// Search without opening a window, printing the results in the order they'd be shown
+    Query {
+        /// What to search for
+        text: String,
+        /// Print the results as JSON, one object per line
+        #[arg(long)]
+        json: bool
+    }
+}
+
+impl Args {
+    // checks what clap can't on its own, the same way the config is checked
+    pub fn validate(&self) -> Result<(), clap::Error> {
+        if let Some(providers) = &self.providers {
+            let (_, problems) = sprint_config::check_result_order(providers.clone());
+            if !problems.is_empty() {
+                return Err(Self::command().error(ErrorKind::ValueValidation, format!("invalid value for --providers: {}", problems.join(", "))));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn apply_to_config(&self, config: &mut SprintConfig) {
+        self.apply_to_search(&mut config.search);
+        if let Some(placeholder) = &self.placeholder {
+            config.placeholder.clone_from(placeholder);
+        }
+        if let Some(width) = self.width {
+            config.width = width;
+        }
+        if let Some(height) = self.height {
+            config.height = height;
+        }
+        if let Some(output) = &self.output {
+            config.output.clone_from(output);
+        }
+    }
+
+    pub fn apply_to_search(&self, search: &mut SearchConfig) {
+        if let Some(providers) = &self.providers {
+            search.result_order.clone_from(providers);
+        }
+        if self.dmenu {
+            search.result_order = vec!["dmenu".to_string()];
+        }
+    }
+
+    pub fn select_action(&self) -> SelectAction {
+        if self.json {
+            SelectAction::Json
+        } else if self.print {
+            SelectAction::Print
+        } else if self.dmenu {
+            SelectAction::Dmenu { print_query: self.print_query }
+        } else {
+            SelectAction::Launch
+        }
+    }
+
+    // anything printing to stdout needs its own instance rather than toggling the daemon, as does
+    // anything the daemon would ignore since it was started with its own options, unless we were
+    // explicitly told to toggle it
+    pub fn needs_own_instance(&self) -> bool {
+        self.daemon || self.dmenu || self.print || self.json || self.command.is_some() || (!self.toggle && self.has_overrides())
+    }
+
+    fn has_overrides(&self) -> bool {
+        self.config.is_some() || self.query.is_some() || self.providers.is_some() || self.placeholder.is_some()
+            || self.width.is_some() || self.height.is_some() || self.output.is_some()
+    }
+
+    pub fn launch_options(&self, dmenu_items: Vec<String>, errors: Vec<SprintError>) -> LaunchOptions {
+        LaunchOptions {
+            daemon: self.daemon,
+            query: self.query.clone().unwrap_or_default(),
+            action: self.select_action(),
+            dmenu_items,
+            errors,
+            config_reload: Some(self.config_reload())
+        }
+    }
+
+    // reloads from the same files, with the same flags over the top, as we were launched with
+    fn config_reload(&self) -> ConfigReload {
+        let args = self.clone();
+        Box::new(move || {
+            let (mut config, errors) = SprintConfig::load(args.config.as_deref())?;
+            args.apply_to_config(&mut config);
+            Ok((config, errors))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use font_kit::font::Font;
+
+    use super::*;
+
+    fn parse(args: &[&str]) -> Args {
+        Args::try_parse_from(std::iter::once("sprint").chain(args.iter().copied())).expect("Failed to parse the arguments.")
+    }
+
+    #[test]
+    fn plain_invocations_toggle_the_daemon() {
+        assert!(!parse(&[]).needs_own_instance());
+        assert!(!parse(&["--toggle"]).needs_own_instance());
+        assert!(!parse(&["-vv", "--log-file"]).needs_own_instance());
+    }
+
+    #[test]
+    fn overrides_and_printing_need_their_own_instance() {
+        for args in [&["--query", "fire"][..], &["--width", "800"], &["--providers", "math"], &["--config", "other.toml"], &["--dmenu"], &["--print"], &["--json"], &["--daemon"], &["query", "fire"]] {
+            assert!(parse(args).needs_own_instance(), "{args:?} should need its own instance");
+        }
+        // unless the daemon was asked for outright
+        assert!(!parse(&["--toggle", "--query", "fire"]).needs_own_instance());
+    }
+
+    #[test]
+    fn providers_are_checked_like_the_config() {
+        assert!(parse(&["--providers", "desktop,Math"]).validate().is_ok());
+        assert_eq!(parse(&["--providers", "apps"]).validate().expect_err("Accepted an unknown provider.").kind(), ErrorKind::ValueValidation);
+        assert_eq!(parse(&["--providers", "math,math"]).validate().expect_err("Accepted a repeated provider.").kind(), ErrorKind::ValueValidation);
+    }
+
+    #[test]
+    fn sizes_below_the_minimum_are_rejected() {
+        assert_eq!(Args::try_parse_from(["sprint", "--width", "99"]).expect_err("Accepted a tiny width.").kind(), ErrorKind::ValueValidation);
+    }
+
+    #[test]
+    fn flags_override_the_config() {
+        let font = Font::from_bytes(Arc::new(include_bytes!("../tests/fonts/DejaVuSans.ttf").to_vec()), 0).expect("Failed to load test font.");
+        let mut config = SprintConfig::with_font(font);
+        parse(&["--providers", "math,desktop", "--placeholder", "Go...", "--width", "800", "--height", "300", "--output", "DP-1"]).apply_to_config(&mut config);
+        assert_eq!(config.search.result_order, vec!["math".to_string(), "desktop".to_string()]);
+        assert_eq!(config.placeholder, "Go...");
+        assert_eq!((config.width, config.height), (800, 300));
+        assert_eq!(config.output, "DP-1");
+
+        // dmenu only ever shows the items it was given
+        parse(&["--dmenu"]).apply_to_config(&mut config);
+        assert_eq!(config.search.result_order, vec!["dmenu".to_string()]);
+    }
+}
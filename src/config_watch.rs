@@ -0,0 +1,62 @@
+use std::{ffi::OsString, io, os::fd::{AsFd, BorrowedFd}, path::{Path, PathBuf}};
+
+use inotify::{Inotify, WatchDescriptor, WatchMask};
+
+// tells us when any of the config files change. the directories are watched rather than the files,
+// as editors tend to save by replacing the file, and a file that doesn't exist yet can't be watched
+pub struct ConfigWatcher {
+    inotify: Inotify,
+    files: Vec<(WatchDescriptor, OsString)>
+}
+impl ConfigWatcher {
+    pub fn new(paths: &[PathBuf]) -> io::Result<Self> {
+        let mut watcher = Self {
+            inotify: Inotify::init()?,
+            files: Vec::new()
+        };
+        watcher.watch(paths)?;
+        Ok(watcher)
+    }
+
+    // watches these files instead of whatever was watched before, as includes come and go
+    pub fn watch(&mut self, paths: &[PathBuf]) -> io::Result<()> {
+        for (descriptor, _) in self.files.drain(..) {
+            // the directory may have been removed, or already unwatched for another file in it
+            let _ = self.inotify.watches().remove(descriptor);
+        }
+        for path in paths {
+            let (Some(directory), Some(file_name)) = (path.parent(), path.file_name()) else {
+                continue;
+            };
+            // there's nothing to watch until the directory is made, which is rare enough to not bother with
+            if !directory.exists() {
+                continue;
+            }
+            let directory = if directory == Path::new("") { Path::new(".") } else { directory };
+            let descriptor = self.inotify.watches().add(directory, WatchMask::CLOSE_WRITE | WatchMask::MOVED_TO | WatchMask::CREATE | WatchMask::DELETE)?;
+            self.files.push((descriptor, file_name.to_os_string()));
+        }
+        Ok(())
+    }
+
+    // reads everything that's happened since last time, returning true if any of it was to our files
+    pub fn changed(&mut self) -> io::Result<bool> {
+        let mut buffer = [0; 4096];
+        let mut changed = false;
+        loop {
+            let events = match self.inotify.read_events(&mut buffer) {
+                Ok(events) => events,
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => return Ok(changed),
+                Err(error) => return Err(error)
+            };
+            for event in events {
+                changed |= event.name.is_some_and(|name| self.files.iter().any(|(descriptor, file_name)| *descriptor == event.wd && file_name == name));
+            }
+        }
+    }
+}
+impl AsFd for ConfigWatcher {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.inotify.as_fd()
+    }
+}
@@ -0,0 +1,94 @@
+use std::{env, fs, io::{self, BufRead, BufReader, Write}, os::unix::net::{UnixListener, UnixStream}, path::PathBuf, time::Duration};
+
+// what a client sends to the daemon to show or hide it
+pub const TOGGLE_COMMAND: &str = "toggle";
+
+// how long a client gets to send its command before we give up on it, as we can't do anything
+// else while waiting
+const READ_TIMEOUT: Duration = Duration::from_millis(200);
+
+// only the runtime dir is private to the user, anywhere shared would let other users toggle or
+// squat on the daemon
+pub fn socket_path() -> io::Result<PathBuf> {
+    match env::var_os("XDG_RUNTIME_DIR") {
+        Some(runtime_dir) if !runtime_dir.is_empty() => Ok(PathBuf::from(runtime_dir).join("sprint.sock")),
+        _ => Err(io::Error::new(io::ErrorKind::NotFound, "XDG_RUNTIME_DIR isn't set, so there's nowhere to put the daemon's socket."))
+    }
+}
+
+// asks a running daemon to show or hide itself, failing if there isn't one
+pub fn send_toggle() -> io::Result<()> {
+    let mut stream = UnixStream::connect(socket_path()?)?;
+    writeln!(stream, "{TOGGLE_COMMAND}")?;
+    stream.flush()
+}
+
+pub fn bind_listener() -> io::Result<UnixListener> {
+    let path = socket_path()?;
+    match UnixListener::bind(&path) {
+        Err(error) if error.kind() == io::ErrorKind::AddrInUse => {
+            // if nothing answers, it's left over from a daemon that didn't get to clean up
+            if UnixStream::connect(&path).is_ok() {
+                return Err(io::Error::new(io::ErrorKind::AddrInUse, "A Sprint daemon is already running."));
+            }
+            fs::remove_file(&path)?;
+            UnixListener::bind(&path)
+        },
+        result => result
+    }
+}
+
+// reads the command a client sent over its connection
+pub fn read_command(stream: UnixStream) -> io::Result<String> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut command = String::new();
+    BufReader::new(stream).read_line(&mut command)?;
+    Ok(command.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stale_socket_is_replaced_but_a_live_one_is_not() {
+        let runtime_dir = env::temp_dir().join(format!("sprint-daemon-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&runtime_dir);
+        fs::create_dir_all(&runtime_dir).expect("Failed to create test runtime dir.");
+        // SAFETY: no other test sets or reads this variable
+        unsafe { env::set_var("XDG_RUNTIME_DIR", &runtime_dir) };
+
+        let listener = bind_listener().expect("Failed to bind the first listener.");
+        let error = bind_listener().expect_err("Bound over a live daemon.");
+        assert_eq!(error.kind(), io::ErrorKind::AddrInUse);
+        // dropping the listener leaves its socket behind, like a daemon that crashed
+        drop(listener);
+        assert!(runtime_dir.join("sprint.sock").exists());
+        let listener = bind_listener().expect("Failed to replace the stale socket.");
+
+        send_toggle().expect("Failed to send a toggle.");
+        let (stream, _) = listener.accept().expect("Failed to accept the client.");
+        assert_eq!(read_command(stream).expect("Failed to read the command."), TOGGLE_COMMAND);
+
+        // SAFETY: as above
+        unsafe { env::set_var("XDG_RUNTIME_DIR", "") };
+        assert_eq!(socket_path().expect_err("Found a socket path without a runtime dir.").kind(), io::ErrorKind::NotFound);
+        // SAFETY: as above
+        unsafe { env::remove_var("XDG_RUNTIME_DIR") };
+    }
+
+    #[test]
+    fn command_is_read_up_to_the_newline() {
+        let (mut client, daemon) = UnixStream::pair().expect("Failed to create a socket pair.");
+        write!(client, "  {TOGGLE_COMMAND}\nanything after").expect("Failed to write the command.");
+        assert_eq!(read_command(daemon).expect("Failed to read the command."), TOGGLE_COMMAND);
+    }
+
+    #[test]
+    fn silent_client_times_out() {
+        // the client stays connected but never sends anything
+        let (_client, daemon) = UnixStream::pair().expect("Failed to create a socket pair.");
+        let error = read_command(daemon).expect_err("Read a command that was never sent.");
+        assert!(matches!(error.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut));
+    }
+}
@@ -1,10 +1,10 @@
-use std::process::{Command, Stdio};
+use std::{path::PathBuf, process::{Command, Stdio}};
 
-use font_kit::font::Font;
 use freedesktop_desktop_entry::{get_languages_from_env, DesktopEntry};
 use pathfinder_geometry::vector::Vector2I;
+use serde::Serialize;
 
-use crate::{render_canvas::CanvasRenderable, text_label::TextLabel};
+use crate::{error::{Result, SprintError}, font::SharedFont, render_canvas::CanvasRenderable, text_label::{TextLabel, Truncation}};
 
 #[derive(Debug)]
 pub enum EntryBoxValue {
@@ -12,6 +12,85 @@ pub enum EntryBoxValue {
     Math(f64),
     WebSearch(String, String),
     WebPrefix(String, String, String),
+    Dmenu(String),
+}
+impl EntryBoxValue {
+    pub fn label(&self) -> String {
+        let locales = get_languages_from_env();
+        match self {
+            // entries without a name are skipped when loading, but fall back to the id just in case
+            EntryBoxValue::Desktop(desktop_entry) => desktop_entry.full_name(&locales).map_or_else(|| desktop_entry.id().to_string(), |name| name.to_string()),
+            EntryBoxValue::Math(math) => format!("= {math}"),
+            EntryBoxValue::WebSearch(query, _) => format!("Search \"{query}\" on the web..."),
+            EntryBoxValue::WebPrefix(name, query, _) => format!("Search \"{query}\" on \"{name}\"..."),
+            EntryBoxValue::Dmenu(item) => item.clone()
+        }
+    }
+
+    // web searches end with where they search, and dmenu items with slashes are likely paths or
+    // urls, where the end matters as much as the start
+    pub fn truncation(&self) -> Truncation {
+        match self {
+            EntryBoxValue::WebSearch(..) | EntryBoxValue::WebPrefix(..) => Truncation::Middle,
+            EntryBoxValue::Dmenu(item) if item.contains('/') => Truncation::Middle,
+            _ => Truncation::End
+        }
+    }
+
+    pub fn describe(&self) -> EntryDescription {
+        let mut description = EntryDescription {
+            kind: "",
+            label: self.label(),
+            path: None,
+            url: None,
+            value: None
+        };
+        match self {
+            EntryBoxValue::Desktop(desktop_entry) => {
+                description.kind = "desktop";
+                description.path = Some(desktop_entry.path.clone());
+            },
+            EntryBoxValue::Math(math) => {
+                description.kind = "math";
+                description.value = Some(*math);
+            },
+            EntryBoxValue::WebSearch(_, url) => {
+                description.kind = "search";
+                description.url = Some(url.clone());
+            },
+            EntryBoxValue::WebPrefix(_, _, url) => {
+                description.kind = "prefix";
+                description.url = Some(url.clone());
+            },
+            EntryBoxValue::Dmenu(_) => description.kind = "dmenu"
+        }
+        description
+    }
+}
+
+// what an entry is, for printing instead of selecting it
+#[derive(Debug, Serialize)]
+pub struct EntryDescription {
+    pub kind: &'static str,
+    pub label: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<f64>
+}
+impl EntryDescription {
+    // the kind, label and whatever the entry points to, tab separated for easy use with cut
+    pub fn to_line(&self) -> String {
+        let target = match (&self.path, &self.url, self.value) {
+            (Some(path), _, _) => path.display().to_string(),
+            (_, Some(url), _) => url.clone(),
+            (_, _, Some(value)) => value.to_string(),
+            _ => self.label.clone()
+        };
+        format!("{}\t{}\t{}", self.kind, self.label, target)
+    }
 }
 
 #[derive(Debug)]
@@ -24,33 +103,37 @@ pub struct Entrybox {
 }
 
 impl Entrybox {
-    pub fn new(value: EntryBoxValue, position: Vector2I, size: Vector2I, font: Font) -> Self {
-        let locales = get_languages_from_env();
-        let label = match value {
-            EntryBoxValue::Desktop(ref desktop_entry) => desktop_entry.full_name(&locales).expect("Failed to get desktop name").to_string(),
-            EntryBoxValue::Math(math) => format!("= {math}"),
-            EntryBoxValue::WebSearch(ref query, _) => format!("Search \"{query}\" on the web..."),
-            EntryBoxValue::WebPrefix(ref name, ref query, _) => format!("Search \"{query}\" on \"{name}\"...")
-        };
+    // the truncation is picked from what the entry is if none is given
+    pub fn new(value: EntryBoxValue, position: Vector2I, size: Vector2I, font: SharedFont, font_size: f32, truncation: Option<Truncation>) -> Self {
+        let mut label = TextLabel::new(&value.label(), font, font_size, position, size);
+        label.set_truncation(truncation.unwrap_or_else(|| value.truncation()));
         Self {
             value,
             position,
             size,
-            label: TextLabel::new(&label, font, 16.0, position, size)
+            label
         }
     }
 
-    pub fn select(&self) {
+    pub fn value(&self) -> &EntryBoxValue {
+        &self.value
+    }
+
+    pub fn describe(&self) -> EntryDescription {
+        self.value.describe()
+    }
+
+    pub fn select(&self) -> Result<()> {
         match &self.value {
             EntryBoxValue::Desktop(desktop_entry) => {
-                println!("{}", desktop_entry.exec().unwrap());
-                let exec = desktop_entry.exec().expect("Desktop entry does not contain an exec.");
+                let exec = desktop_entry.exec().ok_or_else(|| SprintError::MissingExec { name: self.value.label() })?;
+                log::debug!("Running {exec}");
 
                 let command: String = exec.split_whitespace()
                     .filter(|x| !x.starts_with('%'))
                     .collect::<Vec<&str>>()
                     .join(" ");
-                println!("parsed to {command}");
+                log::debug!("Parsed to {command}");
 
                 #[allow(clippy::zombie_processes)]
                 Command::new("sh")
@@ -58,11 +141,15 @@ impl Entrybox {
                     .stdout(Stdio::null())
                     .stderr(Stdio::null())
                     .spawn()
-                    .expect("Unable to launch process");
+                    .map_err(|source| SprintError::Launch { command, source })?;
             },
-            EntryBoxValue::Math(_) => {},
-            EntryBoxValue::WebSearch(_, url) | EntryBoxValue::WebPrefix(_, _, url) => webbrowser::open(url).expect("Failed to launch url on web browser."),
+            // there's nothing to launch, what was picked is printed instead
+            EntryBoxValue::Math(_) | EntryBoxValue::Dmenu(_) => {},
+            EntryBoxValue::WebSearch(_, url) | EntryBoxValue::WebPrefix(_, _, url) => {
+                webbrowser::open(url).map_err(|source| SprintError::OpenUrl { url: url.clone(), source })?;
+            }
         }
+        Ok(())
     }
 }
 
@@ -71,3 +158,32 @@ impl CanvasRenderable for Entrybox {
         self.label.draw(canvas);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_ends_with_what_the_entry_points_to() {
+        assert_eq!(EntryBoxValue::Math(4.5).describe().to_line(), "math\t= 4.5\t4.5");
+        assert_eq!(EntryBoxValue::WebSearch("fire".to_string(), "https://duckduckgo.com/?q=fire".to_string()).describe().to_line(), "search\tSearch \"fire\" on the web...\thttps://duckduckgo.com/?q=fire");
+        assert_eq!(EntryBoxValue::WebPrefix("GitHub".to_string(), "sprint".to_string(), "https://github.com/search?q=sprint".to_string()).describe().to_line(), "prefix\tSearch \"sprint\" on \"GitHub\"...\thttps://github.com/search?q=sprint");
+    }
+
+    #[test]
+    fn line_prefers_the_path_to_anything_else() {
+        let description = EntryDescription {
+            kind: "desktop",
+            label: "Firefox".to_string(),
+            path: Some(PathBuf::from("/usr/share/applications/firefox.desktop")),
+            url: Some("https://example.com".to_string()),
+            value: Some(1.0)
+        };
+        assert_eq!(description.to_line(), "desktop\tFirefox\t/usr/share/applications/firefox.desktop");
+    }
+
+    #[test]
+    fn line_falls_back_to_the_label_without_a_target() {
+        assert_eq!(EntryBoxValue::Dmenu("suspend".to_string()).describe().to_line(), "dmenu\tsuspend\tsuspend");
+    }
+}
@@ -0,0 +1,67 @@
+use std::{fmt, io, path::PathBuf};
+
+use font_kit::error::{FontLoadingError, SelectionError};
+use smithay_client_toolkit::{reexports::calloop, shm::CreatePoolError};
+use thiserror::Error;
+use wayland_client::{globals::{BindError, GlobalError}, ConnectError, DispatchError};
+
+pub type Result<T> = std::result::Result<T, SprintError>;
+
+// everything that can go wrong in sprint. most of these are shown to the user and sprint carries
+// on, only being unable to talk to the compositor or find any font at all is fatal
+#[derive(Debug, Error)]
+pub enum SprintError {
+    #[error("Unable to read config {path}: {source}")]
+    ConfigRead { path: PathBuf, source: io::Error },
+    #[error("{0}")]
+    InvalidConfig(ConfigDiagnostic),
+    #[error("{variable}: {message}")]
+    InvalidEnvConfig { variable: String, message: String },
+    #[error("Unable to create default config file {path}: {source}")]
+    ConfigCreate { path: PathBuf, source: io::Error },
+    #[error("Unable to find font {name}: {source}")]
+    FontSelection { name: String, source: SelectionError },
+    #[error("Failed to load font: {0}")]
+    FontLoad(#[from] FontLoadingError),
+
+    #[error("{name} has no command to run")]
+    MissingExec { name: String },
+    #[error("Unable to launch {command}: {source}")]
+    Launch { command: String, source: io::Error },
+    #[error("Unable to open {url} in a web browser: {source}")]
+    OpenUrl { url: String, source: io::Error },
+
+    #[error("Unable to connect to a compositor: {0}")]
+    Connect(#[from] ConnectError),
+    #[error("Failed to fetch globals from the compositor: {0}")]
+    Globals(#[from] GlobalError),
+    #[error("Compositor does not support '{interface}': {source}")]
+    Unsupported { interface: &'static str, source: BindError },
+    #[error("Lost connection to the compositor: {0}")]
+    Dispatch(#[from] DispatchError),
+    #[error("Failed to create shared memory pool: {0}")]
+    Pool(#[from] CreatePoolError),
+    #[error("Event loop failed: {0}")]
+    EventLoop(#[from] calloop::Error),
+    #[error("Failed to listen on the daemon socket: {0}")]
+    DaemonSocket(io::Error)
+}
+
+// something wrong with the config, and where, so it can be found and fixed
+#[derive(Debug)]
+pub struct ConfigDiagnostic {
+    pub path: PathBuf,
+    pub line: usize,
+    // none if the problem isn't with a particular key, like a syntax error
+    pub key: Option<String>,
+    pub message: String
+}
+impl fmt::Display for ConfigDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: ", self.path.display(), self.line)?;
+        if let Some(key) = &self.key {
+            write!(f, "{key}: ")?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
@@ -0,0 +1,78 @@
+use std::{ops::Deref, rc::Rc, sync::atomic::{AtomicUsize, Ordering}};
+
+use font_kit::{canvas::{Canvas, Format, RasterizationOptions}, font::Font, hinting::HintingOptions};
+use pathfinder_geometry::{rect::RectI, transform2d::Transform2F, vector::{Vector2F, Vector2I}};
+
+// a loaded font, shared by every label using it rather than each having its own copy. font-kit's
+// fonts can't be sent between threads so this is an rc, but the glyphs rasterized from it can be
+// and are cached by its id
+#[derive(Clone, Debug)]
+pub struct SharedFont {
+    font: Rc<Font>,
+    id: usize
+}
+impl SharedFont {
+    pub fn new(font: Font) -> Self {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+        Self {
+            font: Rc::new(font),
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed)
+        }
+    }
+
+    // tells fonts apart, as the same font loaded twice could still be configured differently
+    pub fn id(&self) -> usize {
+        self.id
+    }
+}
+impl Deref for SharedFont {
+    type Target = Font;
+
+    fn deref(&self) -> &Font {
+        &self.font
+    }
+}
+
+// a glyph rasterized on its own, ready to be copied wherever it's drawn
+#[derive(Debug)]
+pub struct RasterizedGlyph {
+    // where the glyph's raster bounds are relative to the pen position, what the pen advances by
+    pub bounds: RectI,
+    // where the coverage below goes relative to the pen position, a little bigger than the bounds
+    // as glyphs can spill out of them
+    pub coverage_rect: RectI,
+    pub coverage: Vec<u8>
+}
+
+// room around the raster bounds for anything drawn outside of them
+const GLYPH_PADDING: i32 = 2;
+
+// rasterizes the glyph with the pen the given fraction of a pixel into a pixel, none if font-kit
+// can't rasterize it
+pub fn rasterize_glyph(font: &Font, glyph_id: u32, font_size: f32, subpixel_offset: Vector2F) -> Option<RasterizedGlyph> {
+    let transform = Transform2F::from_translation(subpixel_offset);
+    // BUG: this fails with some characters, i believe this is a font kit issue?
+    // https://github.com/servo/font-kit/issues/253
+    // until then, skip them rather than taking the whole launcher down
+    let bounds = match font.raster_bounds(glyph_id, font_size, transform, HintingOptions::None, RasterizationOptions::GrayscaleAa) {
+        Ok(bounds) => bounds,
+        Err(error) => {
+            log::warn!("Unable to find bounds for glyph {glyph_id}, skipping it: {error:?}");
+            return None;
+        }
+    };
+
+    let padding = Vector2I::splat(GLYPH_PADDING);
+    let coverage_rect = RectI::new(bounds.origin() - padding, bounds.size() + padding * 2);
+    let mut canvas = Canvas::new(coverage_rect.size(), Format::A8);
+    let origin = -coverage_rect.origin();
+    let transform = Transform2F::from_translation(origin.to_f32()) * transform;
+    if let Err(error) = font.rasterize_glyph(&mut canvas, glyph_id, font_size, transform, HintingOptions::None, RasterizationOptions::GrayscaleAa) {
+        log::warn!("Unable to render glyph {glyph_id}: {error:?}");
+    }
+    Some(RasterizedGlyph {
+        bounds,
+        coverage_rect,
+        coverage: canvas.pixels
+    })
+}
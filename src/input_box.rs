@@ -1,9 +1,8 @@
 use std::cmp::{max, min};
 
-use font_kit::font::Font;
-use pathfinder_geometry::vector::Vector2I;
+use pathfinder_geometry::{rect::RectI, vector::Vector2I};
 
-use crate::{render_canvas::{CanvasRenderable, Color}, text_label::TextLabel};
+use crate::{font::SharedFont, render_canvas::{CanvasRenderable, Color}, text_label::{TextLabel, Truncation}};
 
 #[allow(dead_code)]
 pub struct InputBox {
@@ -12,32 +11,50 @@ pub struct InputBox {
     placeholder: String,
     text: String,
     cursor_pos: usize,
+    // how far the text is scrolled left to keep the cursor in view, in actual pixels
+    scroll: u32,
+    // text an input method is still composing, shown at the cursor but not part of the text yet
+    preedit: String,
+    // where the input method's cursor is in the preedit in chars, none if it wants it hidden
+    preedit_cursor: Option<usize>,
+    // where the cursor was last drawn, in logical pixels
+    cursor_rect: RectI,
 
     label: TextLabel,
     placeholder_label: TextLabel
 }
 
 impl InputBox {
-    pub fn new(starting_text: &str, placeholder: &str, position: Vector2I, size: Vector2I, font: &Font) -> Self {
+    pub fn new(starting_text: &str, placeholder: &str, position: Vector2I, size: Vector2I, font: &SharedFont, font_size: f32) -> Self {
+        // the text scrolls to follow the cursor rather than being cut short
+        let mut label = TextLabel::new(starting_text, font.clone(), font_size, position, size);
+        label.set_truncation(Truncation::Clip);
         Self {
             position,
             size,
             placeholder: placeholder.to_string(),
             text: starting_text.to_string(),
             cursor_pos: 0,
-            label: TextLabel::new(starting_text, font.clone(), 18.0, position, size),
-            placeholder_label: TextLabel::new(placeholder, font.clone(), 18.0, position, size)
+            scroll: 0,
+            preedit: String::new(),
+            preedit_cursor: None,
+            cursor_rect: RectI::new(position, Vector2I::new(1, size.y())),
+            label,
+            placeholder_label: TextLabel::new(placeholder, font.clone(), font_size, position, size)
         }
     }
 
     pub fn set_cursor_pos(&mut self, pos: usize) {
         self.cursor_pos = max(min(pos, self.text.chars().count()), 0);
     }
-    pub fn advance_cursor(&mut self) {
-        self.set_cursor_pos(self.cursor_pos + 1);
+    // moves the cursor one char along on screen, which is backwards through right to left text
+    pub fn cursor_left(&mut self) {
+        let pos = self.label.visual_neighbour(self.cursor_pos, false);
+        self.set_cursor_pos(pos);
     }
-    pub fn reel_cursor(&mut self) {
-        self.set_cursor_pos(self.cursor_pos - 1);
+    pub fn cursor_right(&mut self) {
+        let pos = self.label.visual_neighbour(self.cursor_pos, true);
+        self.set_cursor_pos(pos);
     }
     pub fn set_cursor_to_home(&mut self) {
         self.set_cursor_pos(0);
@@ -46,34 +63,179 @@ impl InputBox {
         self.set_cursor_pos(self.text.chars().count());
     }
 
+    // the text and where the cursor is in it in bytes, for input methods to work around
+    pub fn surrounding_text(&self) -> (&str, usize) {
+        (&self.text, self.byte_index(self.cursor_pos))
+    }
+    pub fn cursor_rect(&self) -> RectI {
+        self.cursor_rect
+    }
+
+    // replaces all of the text, leaving the cursor at the end
+    pub fn set_text(&mut self, text: &str) {
+        self.text = text.to_string();
+        self.preedit.clear();
+        self.refresh_label();
+        self.set_cursor_to_end();
+    }
+
+    // shows what an input method is composing at the cursor, with its cursor given in bytes
+    pub fn set_preedit(&mut self, preedit: &str, cursor: Option<usize>) {
+        self.preedit = preedit.to_string();
+        self.preedit_cursor = cursor.map(|cursor| preedit.char_indices().take_while(|(index, _)| *index < cursor).count());
+        self.refresh_label();
+    }
+
     pub fn push_at_cursor(&mut self, ch: char) -> String {
-        self.text.insert(self.cursor_pos, ch);
-        self.label.set_text(&self.text);
-        self.cursor_pos += 1;
+        self.insert_at_cursor(ch.encode_utf8(&mut [0; 4]))
+    }
+    pub fn insert_at_cursor(&mut self, text: &str) -> String {
+        self.text.insert_str(self.byte_index(self.cursor_pos), text);
+        self.refresh_label();
+        self.cursor_pos += text.chars().count();
 
-        self.text.to_string()
+        self.text.clone()
     }
     pub fn pop_at_cursor(&mut self) -> Option<String> {
         if self.text.is_empty() || self.cursor_pos == 0 {
             return None;
         }
-        self.text.remove(self.cursor_pos - 1);
-        self.label.set_text(&self.text);
+        self.text.remove(self.byte_index(self.cursor_pos - 1));
+        self.refresh_label();
         self.cursor_pos -= 1;
-        Some(self.text.to_string())
+        Some(self.text.clone())
+    }
+    // removes the given number of bytes either side of the cursor, as input methods ask for
+    pub fn delete_around_cursor(&mut self, before: usize, after: usize) -> String {
+        let cursor = self.byte_index(self.cursor_pos);
+        let mut start = cursor.saturating_sub(before);
+        let mut end = min(cursor + after, self.text.len());
+        // never split a char, whatever we're asked
+        while !self.text.is_char_boundary(start) {
+            start -= 1;
+        }
+        while !self.text.is_char_boundary(end) {
+            end += 1;
+        }
+        self.text.replace_range(start..end, "");
+        self.cursor_pos = self.text[..start].chars().count();
+        self.refresh_label();
+        self.text.clone()
+    }
+
+    // the cursor counts chars, but the text is indexed by bytes
+    fn byte_index(&self, pos: usize) -> usize {
+        self.text.char_indices().nth(pos).map_or(self.text.len(), |(index, _)| index)
+    }
+
+    fn refresh_label(&mut self) {
+        if self.preedit.is_empty() {
+            self.label.set_text(&self.text);
+        } else {
+            let cursor = self.byte_index(self.cursor_pos);
+            self.label.set_text(&format!("{}{}{}", &self.text[..cursor], self.preedit, &self.text[cursor..]));
+        }
     }
 }
 impl CanvasRenderable for InputBox {
+    #[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss, clippy::cast_sign_loss)]
     fn draw(&mut self, canvas: &mut crate::render_canvas::RenderCanvas) {
-        if self.text.is_empty() {
+        // the cursor goes wherever the input method has it in the preedit, if anywhere
+        let preedit_length = self.preedit.chars().count();
+        let cursor_place = self.cursor_pos + self.preedit_cursor.unwrap_or(preedit_length);
+        let show_cursor = self.preedit.is_empty() || self.preedit_cursor.is_some();
+
+        // keep the cursor in view, scrolling no further than needed to. this all measures in
+        // already scaled pixels
+        let cursor_width = max(canvas.scale_value(1), 1);
+        let cursor_length = self.label.find_cursor_length(cursor_place, canvas.scale());
+        let visible_width = canvas.scale_value(self.size.x() as u32).saturating_sub(cursor_width);
+        if cursor_length < self.scroll {
+            self.scroll = cursor_length;
+        } else if cursor_length > self.scroll + visible_width {
+            self.scroll = cursor_length - visible_width;
+        }
+        // don't leave empty space at the end when the text gets shorter
+        self.scroll = self.scroll.min(self.label.text_width(canvas.scale()).saturating_sub(visible_width));
+        self.label.set_scroll(self.scroll);
+
+        if self.text.is_empty() && self.preedit.is_empty() {
             self.placeholder_label.draw(canvas);
         } else {
             self.label.draw(canvas);
         }
 
-        // the label will have updated it's bounds cache by this point so we don't need to worry
-        // about calling it explicitly
-        #[allow(clippy::cast_sign_loss)]
-        canvas.draw_box(self.position.x() as u32 + (self.label.find_cursor_length(self.cursor_pos)), self.position.y() as u32, 1, self.size.y() as u32, Color::new_mono(255, 255));
+        let (x, y, height) = (
+            canvas.scale_value(self.position.x() as u32),
+            canvas.scale_value(self.position.y() as u32),
+            canvas.scale_value(self.size.y() as u32)
+        );
+        if !self.preedit.is_empty() {
+            // underline the preedit, so it's clear it isn't part of the query yet. right to left
+            // text can put its start after its end
+            let start = self.label.find_cursor_length(self.cursor_pos, canvas.scale());
+            let end = self.label.find_cursor_length(self.cursor_pos + preedit_length, canvas.scale());
+            let (start, end) = (min(start, end).max(self.scroll), max(start, end).min(self.scroll + visible_width));
+            if end > start {
+                let underline_y = y + canvas.scale_value(self.size.y() as u32 * 3 / 4);
+                canvas.draw_box(x + start - self.scroll, underline_y, end - start, cursor_width, Color::new_mono(255, 255));
+            }
+        }
+
+        let cursor_x = x + cursor_length - self.scroll;
+        if show_cursor {
+            canvas.draw_box(cursor_x, y, cursor_width, height, Color::new_mono(255, 255));
+        }
+        let logical_x = (cursor_x as f32 / canvas.scale()).round() as i32;
+        self.cursor_rect = RectI::new(Vector2I::new(logical_x, self.position.y()), Vector2I::new(1, self.size.y()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use font_kit::font::Font;
+
+    use super::*;
+
+    fn with_text(text: &str, cursor_pos: usize) -> InputBox {
+        let font = Font::from_bytes(Arc::new(include_bytes!("../tests/fonts/DejaVuSans.ttf").to_vec()), 0).expect("Failed to load test font.");
+        let mut input_box = InputBox::new(text, "", Vector2I::zero(), Vector2I::new(200, 40), &SharedFont::new(font), 16.0);
+        input_box.set_cursor_pos(cursor_pos);
+        input_box
+    }
+
+    #[test]
+    fn delete_at_the_start() {
+        let mut input_box = with_text("hello", 0);
+        assert_eq!(input_box.delete_around_cursor(2, 2), "llo");
+        assert_eq!(input_box.surrounding_text(), ("llo", 0));
+    }
+
+    #[test]
+    fn delete_at_the_end() {
+        let mut input_box = with_text("hello", 5);
+        assert_eq!(input_box.delete_around_cursor(2, 3), "hel");
+        assert_eq!(input_box.surrounding_text(), ("hel", 3));
+    }
+
+    #[test]
+    fn delete_never_splits_a_char() {
+        // ñ is two bytes, and deleting one byte either side of it takes the whole char
+        let mut input_box = with_text("añb", 2);
+        assert_eq!(input_box.delete_around_cursor(1, 0), "ab");
+        assert_eq!(input_box.surrounding_text(), ("ab", 1));
+
+        let mut input_box = with_text("añb", 1);
+        assert_eq!(input_box.delete_around_cursor(0, 1), "ab");
+        assert_eq!(input_box.surrounding_text(), ("ab", 1));
+    }
+
+    #[test]
+    fn delete_past_either_end_stops_there() {
+        let mut input_box = with_text("hello", 2);
+        assert_eq!(input_box.delete_around_cursor(10, 10), "");
+        assert_eq!(input_box.surrounding_text(), ("", 0));
     }
 }
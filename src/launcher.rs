@@ -0,0 +1,328 @@
+use std::cmp::min;
+
+use pathfinder_geometry::{rect::RectI, vector::Vector2I};
+
+use crate::{entry_box::{EntryBoxValue, Entrybox}, error::SprintError, input_box::InputBox, render_canvas::{CanvasRenderable, Color, RenderCanvas}, results::SprintResults, sprint_config::SprintConfig, text_label::{TextLabel, Truncation}, timing};
+
+// everything sprint shows, drawn onto a canvas without needing a compositor. wayland feeds it
+// input and copies the canvas out, but it can just as well be drawn offscreen and saved
+pub struct Launcher {
+    config: SprintConfig,
+    width: u32,
+    height: u32,
+    canvas: RenderCanvas,
+    redraw: Redraw,
+
+    // App Data
+    filter: String,
+    filter_results: SprintResults,
+    selected: usize,
+
+    // Components
+    filter_input: InputBox,
+    filter_results_cache: Vec<Entrybox>,
+    no_results_label: TextLabel,
+    // the last thing that went wrong, shown along the bottom until we're reset
+    error_label: Option<TextLabel>
+}
+
+impl Launcher {
+    pub fn new(config: SprintConfig, query: &str, filter_results: SprintResults) -> Self {
+        let width = config.width;
+        let height = config.height;
+        let width_int = i32::try_from(width).expect("width to i32 failed");
+        let height_int = i32::try_from(height).expect("height to i32 failed");
+
+        let mut launcher = Self {
+            width,
+            height,
+            canvas: RenderCanvas::new(width, height, 1.0),
+            redraw: Redraw { full: true, ..Default::default() },
+
+            filter: query.to_string(),
+            filter_results,
+            selected: 0,
+
+            filter_input: InputBox::new(query, &config.placeholder, Vector2I::new(16, 8), Vector2I::new(width_int - 28, 32), &config.font, config.input_font_size),
+            filter_results_cache: Vec::new(),
+            no_results_label: TextLabel::new("¯\\_(._.)_/¯", config.font.clone(), 18.0, Vector2I::new((width_int - 100) / 2, (height_int - 32) / 2), Vector2I::new(100, 32)),
+            error_label: None,
+            config
+        };
+        launcher.filter_input.set_cursor_to_end();
+        launcher.refresh_results();
+        launcher
+    }
+
+    pub fn config(&self) -> &SprintConfig {
+        &self.config
+    }
+    pub fn canvas(&self) -> &RenderCanvas {
+        &self.canvas
+    }
+    pub fn canvas_mut(&mut self) -> &mut RenderCanvas {
+        &mut self.canvas
+    }
+    pub fn filter(&self) -> &str {
+        &self.filter
+    }
+    pub fn selected_entry(&self) -> Option<&Entrybox> {
+        self.filter_results_cache.get(self.selected)
+    }
+    pub fn needs_redraw(&self) -> bool {
+        self.redraw.is_pending()
+    }
+
+    // width and height are logical, the canvas is made that much bigger again by the scale
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss, clippy::cast_precision_loss)]
+    pub fn resize(&mut self, width: u32, height: u32, scale: f32) {
+        self.width = width;
+        self.height = height;
+        let scaled_width = (width as f32 * scale).round() as u32;
+        let scaled_height = (height as f32 * scale).round() as u32;
+        self.canvas = RenderCanvas::new(scaled_width, scaled_height, scale);
+        self.redraw.full = true;
+    }
+
+    // swaps in a new config, rebuilding everything drawn with the old one but keeping what's been typed
+    pub fn set_config(&mut self, config: SprintConfig) {
+        let scale = self.canvas.scale();
+        let filter_results = std::mem::take(&mut self.filter_results);
+        *self = Self::new(config, &self.filter, filter_results);
+        self.resize(self.width, self.height, scale);
+    }
+
+    // starts over with the given query, as if we'd just been launched
+    pub fn reset(&mut self, query: &str) {
+        self.filter_results.rescan_desktop_entries();
+        self.filter_input.set_text(query);
+        self.filter = query.to_string();
+        self.selected = 0;
+        self.error_label = None;
+        self.refresh_results();
+        self.redraw.full = true;
+    }
+
+    // shows the error in a banner rather than crashing over it
+    pub fn show_error(&mut self, error: &SprintError) {
+        self.show_banner(&error.to_string());
+    }
+
+    // shows the first of several errors, pointing to the log for the rest
+    pub fn show_errors(&mut self, errors: &[SprintError]) {
+        match errors {
+            [] => {},
+            [error] => self.show_error(error),
+            [error, rest @ ..] => self.show_banner(&format!("{error} (and {} more, see the log)", rest.len()))
+        }
+    }
+
+    fn show_banner(&mut self, message: &str) {
+        let width_int = i32::try_from(self.width).expect("width to i32 failed");
+        let banner_y = i32::try_from(self.banner_y()).expect("banner y to i32 failed");
+        let banner_height = i32::try_from(BANNER_HEIGHT).expect("banner height to i32 failed");
+        let mut error_label = TextLabel::new(message, self.config.font.clone(), 16.0, Vector2I::new(16, banner_y), Vector2I::new(width_int - 32, banner_height));
+        // keeps the count of other errors on the end in view
+        error_label.set_truncation(Truncation::Middle);
+        self.error_label = Some(error_label);
+        self.redraw.results = true;
+    }
+
+    pub fn push_char(&mut self, character: char) {
+        let filter = self.filter_input.push_at_cursor(character);
+        self.set_filter(filter);
+    }
+    pub fn pop_char(&mut self) {
+        if let Some(filter) = self.filter_input.pop_at_cursor() {
+            self.set_filter(filter);
+        }
+        self.redraw.input = true;
+    }
+
+    // input methods send whole strings at a time, rather than a char per key
+    pub fn insert_text(&mut self, text: &str) {
+        let filter = self.filter_input.insert_at_cursor(text);
+        self.set_filter(filter);
+    }
+    pub fn delete_around_cursor(&mut self, before: usize, after: usize) {
+        let filter = self.filter_input.delete_around_cursor(before, after);
+        self.set_filter(filter);
+    }
+    // what an input method is still composing doesn't filter anything until it's committed
+    pub fn set_preedit(&mut self, preedit: &str, cursor: Option<usize>) {
+        self.filter_input.set_preedit(preedit, cursor);
+        self.redraw.input = true;
+    }
+    pub fn surrounding_text(&self) -> (&str, usize) {
+        self.filter_input.surrounding_text()
+    }
+    // where the cursor was last drawn in logical pixels, for input methods to put their popups by
+    pub fn cursor_rect(&self) -> RectI {
+        self.filter_input.cursor_rect()
+    }
+
+    pub fn cursor_left(&mut self) {
+        self.filter_input.cursor_left();
+        self.redraw.input = true;
+    }
+    pub fn cursor_right(&mut self) {
+        self.filter_input.cursor_right();
+        self.redraw.input = true;
+    }
+    pub fn cursor_home(&mut self) {
+        self.filter_input.set_cursor_to_home();
+        self.redraw.input = true;
+    }
+    pub fn cursor_end(&mut self) {
+        self.filter_input.set_cursor_to_end();
+        self.redraw.input = true;
+    }
+
+    pub fn select_next(&mut self) {
+        self.set_selected(min(self.last_result_index(), self.selected + 1));
+    }
+    pub fn select_previous(&mut self) {
+        self.set_selected(self.selected.saturating_sub(1));
+    }
+
+    fn set_selected(&mut self, selected: usize) {
+        if selected != self.selected {
+            self.redraw.rows.extend([self.selected, selected]);
+            self.selected = selected;
+        }
+    }
+
+    fn last_result_index(&self) -> usize {
+        self.filter_results_cache.len().saturating_sub(1)
+    }
+
+    fn set_filter(&mut self, filter: String) {
+        // only a changed filter redoes the results
+        self.redraw.input = true;
+        if filter != self.filter {
+            self.filter = filter;
+            self.refresh_results();
+            self.redraw.results = true;
+        }
+    }
+
+    fn refresh_results(&mut self) {
+        self.filter_results.refresh_results(&self.filter, &self.config.search);
+        self.recreate_results_cache();
+        self.selected = min(self.selected, self.last_result_index());
+    }
+
+    fn recreate_results_cache(&mut self) {
+        let _span = timing::span("Recreating results element cache");
+        let mut transform = Vector2I::new(16, i32::try_from(RESULTS_Y).expect("results y to i32 failed"));
+        // the same margin on the right as on the left, so long labels are cut short before the edge
+        let standard_size = Vector2I::new(i32::try_from(self.width).expect("width to i32 failed") - 32, HEIGHT_PER_ELEMENT);
+        self.filter_results_cache = self.filter_results.ordered_results(&self.config.search).into_iter()
+            .map(|value| {
+                let font = if matches!(value, EntryBoxValue::Math(_)) { &self.config.monospace_font } else { &self.config.font };
+                let entry = Entrybox::new(value, transform, standard_size, font.clone(), self.config.result_font_size, self.config.truncation);
+                transform.set_y(transform.y() + HEIGHT_PER_ELEMENT);
+                entry
+            })
+            .collect();
+    }
+
+    // draws whatever has changed since the last draw onto the canvas, damaging what it touches
+    pub fn draw(&mut self) {
+        let _span = timing::span("Render");
+        // Call your component draw calls here, in order you want them to display
+        let redraw = std::mem::take(&mut self.redraw);
+        if redraw.full {
+            self.canvas.wipe(self.config.background_color);
+            self.draw_input();
+            self.draw_results();
+        } else {
+            if redraw.input {
+                self.draw_input();
+            }
+            if redraw.results {
+                self.draw_results();
+            } else {
+                for row in redraw.rows {
+                    self.draw_row(row);
+                }
+            }
+        }
+    }
+
+    fn draw_input(&mut self) {
+        self.canvas.draw_scaled_box(0, 0, self.width, INPUT_HEIGHT, self.config.foreground_color);
+        self.filter_input.draw(&mut self.canvas);
+        self.canvas.damage_scaled(0, 0, self.width, INPUT_HEIGHT);
+    }
+
+    fn draw_results(&mut self) {
+        let results_height = self.height.saturating_sub(RESULTS_Y);
+        self.canvas.draw_scaled_box(0, RESULTS_Y, self.width, results_height, self.config.background_color);
+
+        let selected_height = HEIGHT_PER_ELEMENT * i32::try_from(self.selected).expect("selected to i32 failed");
+        if self.filter_results_cache.is_empty() {
+            self.no_results_label.draw(&mut self.canvas);
+        } else {
+            self.canvas.draw_scaled_box(0, RESULTS_Y + u32::try_from(selected_height).expect("selected height to u32 failed"), self.width, HEIGHT_PER_ELEMENT as u32, self.config.selection_hover_color);
+        }
+        self.canvas.draw_scaled_box(0, RESULTS_Y, self.width, 1, self.config.seperator_color);
+
+        for x in &mut self.filter_results_cache {
+            x.draw(&mut self.canvas);
+        }
+        self.draw_error_banner();
+        self.canvas.damage_scaled(0, RESULTS_Y, self.width, results_height);
+    }
+
+    fn draw_error_banner(&mut self) {
+        let banner_y = self.banner_y();
+        if let Some(error_label) = &mut self.error_label {
+            self.canvas.draw_scaled_box(0, banner_y, self.width, BANNER_HEIGHT, ERROR_COLOR);
+            error_label.draw(&mut self.canvas);
+        }
+    }
+
+    fn banner_y(&self) -> u32 {
+        self.height.saturating_sub(BANNER_HEIGHT)
+    }
+
+    // redraws a single result row, for when only the selection has moved
+    fn draw_row(&mut self, row: usize) {
+        let y = RESULTS_Y + u32::try_from(row).expect("row to u32 failed") * HEIGHT_PER_ELEMENT as u32;
+        let color = if row == self.selected { self.config.selection_hover_color } else { self.config.background_color };
+        self.canvas.draw_scaled_box(0, y, self.width, HEIGHT_PER_ELEMENT as u32, color);
+        if row == 0 {
+            self.canvas.draw_scaled_box(0, RESULTS_Y, self.width, 1, self.config.seperator_color);
+        }
+        if let Some(entry) = self.filter_results_cache.get_mut(row) {
+            entry.draw(&mut self.canvas);
+        }
+        // the banner sits on top of any rows that reach down to it
+        if y + HEIGHT_PER_ELEMENT as u32 > self.banner_y() {
+            self.draw_error_banner();
+        }
+        self.canvas.damage_scaled(0, y, self.width, HEIGHT_PER_ELEMENT as u32);
+    }
+}
+
+// what needs redrawing on the next draw, anything not in here is left as it was on the canvas
+#[derive(Default)]
+struct Redraw {
+    full: bool,
+    input: bool,
+    results: bool,
+    // rows whose selection highlight has changed
+    rows: Vec<usize>
+}
+impl Redraw {
+    fn is_pending(&self) -> bool {
+        self.full || self.input || self.results || !self.rows.is_empty()
+    }
+}
+
+const INPUT_HEIGHT: u32 = 48;
+const RESULTS_Y: u32 = 49;
+const HEIGHT_PER_ELEMENT: i32 = 30;
+const BANNER_HEIGHT: u32 = 30;
+const ERROR_COLOR: Color = Color { r: 140, g: 36, b: 48, a: 255 };
@@ -0,0 +1,21 @@
+//! Sprint's search, rendering and widgets, used by the `sprint` binary and usable on their own.
+#![warn(clippy::pedantic)]
+#![warn(clippy::redundant_type_annotations)]
+// the api is commented the same way as the rest of sprint, rather than with rustdoc sections and
+// must_use on every getter
+#![allow(clippy::missing_panics_doc, clippy::missing_errors_doc, clippy::must_use_candidate)]
+
+pub mod config_watch;
+pub mod daemon;
+pub mod entry_box;
+pub mod error;
+pub mod font;
+pub mod input_box;
+pub mod launcher;
+pub mod render_canvas;
+pub mod results;
+pub mod sprint_config;
+pub mod text_input;
+pub mod text_label;
+pub mod timing;
+pub mod wayland;
@@ -0,0 +1,58 @@
+use std::{env, fs::{self, File}, io::{self, Write}, path::PathBuf};
+
+use env_logger::{Builder, Target};
+use log::LevelFilter;
+
+// takes env_logger style filters, e.g. "debug" or "sprint::timing=debug"
+const LOG_ENV: &str = "SPRINT_LOG";
+
+// logs to stderr, and to the log file as well if asked to. SPRINT_LOG wins over --verbose
+pub fn init(verbosity: u8, log_file: bool) {
+    let level = match verbosity {
+        0 => LevelFilter::Warn,
+        1 => LevelFilter::Info,
+        2 => LevelFilter::Debug,
+        _ => LevelFilter::Trace
+    };
+    let mut builder = Builder::new();
+    match env::var(LOG_ENV) {
+        Ok(filters) => builder.parse_filters(&filters),
+        // our dependencies get chatty below warnings, so only turn ourselves up
+        Err(_) => builder.filter_level(LevelFilter::Warn).filter_module("sprint", level)
+    };
+
+    if log_file {
+        let path = log_file_path();
+        // there's no logger yet to complain through, and logging to the file isn't worth failing over
+        match fs::create_dir_all(path.parent().expect("log file has no parent")).and_then(|()| File::options().create(true).append(true).open(&path)) {
+            Ok(file) => {
+                builder.target(Target::Pipe(Box::new(Tee(file))));
+            },
+            Err(error) => eprintln!("Error: Unable to open log file {}: {error}", path.display())
+        }
+    }
+    builder.init();
+}
+
+fn log_file_path() -> PathBuf {
+    let state_home = env::var("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|_| env::var("HOME").map(|home| PathBuf::from(home).join(".local/state")))
+        .unwrap_or_else(|_| env::temp_dir());
+    state_home.join("sprint").join("sprint.log")
+}
+
+// writes to stderr as well as the file, so logging to a file doesn't hide anything from the terminal
+struct Tee(File);
+impl Write for Tee {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        io::stderr().write_all(buf)?;
+        self.0.write_all(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stderr().flush()?;
+        self.0.flush()
+    }
+}
@@ -1,17 +1,118 @@
 #![warn(clippy::pedantic)]
 #![warn(clippy::redundant_type_annotations)]
 
-use sprint_config::SprintConfig;
-
-mod entry_box;
-mod input_box;
-mod render_canvas;
-mod results;
-mod sprint_config;
-mod text_label;
-mod wayland;
-
-fn main() {
-    let config = SprintConfig::load();
-    wayland::create_layer(config);
+use std::{io::{self, BufRead}, process::ExitCode};
+
+use clap::Parser;
+use cli::{Args, Command};
+use sprint::{daemon, results::SprintResults, sprint_config::{self, SearchConfig, SprintConfig}, wayland::{self, SelectAction}};
+
+mod cli;
+mod logging;
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    if let Err(error) = args.validate() {
+        error.exit();
+    }
+    logging::init(args.verbose, args.log_file);
+    if args.print_default_config {
+        print!("{}", sprint_config::DEFAULT_CONFIG_CONTENTS);
+        return ExitCode::SUCCESS;
+    }
+    if args.check_config {
+        return check_config(&args);
+    }
+    if !args.needs_own_instance() {
+        // if there's a daemon running it can show itself far quicker than we can start up
+        match daemon::send_toggle() {
+            Ok(()) => return ExitCode::SUCCESS,
+            Err(error) if args.toggle => {
+                log::error!("Unable to reach the Sprint daemon: {error}");
+                return ExitCode::FAILURE;
+            },
+            Err(_) => {}
+        }
+    }
+
+    let dmenu_items = if args.dmenu {
+        io::stdin().lock().lines()
+            .map_while(Result::ok)
+            .filter(|line| !line.is_empty())
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    if let Some(Command::Query { text, json }) = &args.command {
+        print_query_results(&args, text, dmenu_items, *json || args.json);
+        return ExitCode::SUCCESS;
+    }
+
+    let (mut config, errors) = match SprintConfig::load(args.config.as_deref()) {
+        Ok(loaded) => loaded,
+        Err(error) => {
+            log::error!("{error}");
+            return ExitCode::FAILURE;
+        }
+    };
+    for error in &errors {
+        log::error!("{error}");
+    }
+    args.apply_to_config(&mut config);
+
+    let made_selection = match wayland::create_layer(config, args.launch_options(dmenu_items, errors)) {
+        Ok(made_selection) => made_selection,
+        Err(error) => {
+            log::error!("{error}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    // like dmenu, let scripts know if the user backed out
+    if !matches!(args.select_action(), SelectAction::Launch) && !made_selection {
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
+
+// prints every problem with the config, failing if there are any
+fn check_config(args: &Args) -> ExitCode {
+    match SprintConfig::load(args.config.as_deref()) {
+        Ok((_, errors)) if errors.is_empty() => {
+            println!("No problems found");
+            ExitCode::SUCCESS
+        },
+        Ok((_, errors)) => {
+            for error in &errors {
+                println!("{error}");
+            }
+            ExitCode::FAILURE
+        },
+        Err(error) => {
+            println!("{error}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+// runs a search without touching wayland or fonts at all, for scripts and debugging
+fn print_query_results(args: &Args, text: &str, dmenu_items: Vec<String>, json: bool) {
+    let (mut config, errors) = SearchConfig::load(args.config.as_deref());
+    for error in &errors {
+        log::error!("{error}");
+    }
+    args.apply_to_search(&mut config);
+
+    let mut results = SprintResults::new();
+    results.set_dmenu_items(dmenu_items);
+    results.refresh_results(text, &config);
+    for result in results.ordered_results(&config) {
+        let description = result.describe();
+        if json {
+            println!("{}", serde_json::to_string(&description).expect("Failed to serialize result."));
+        } else {
+            println!("{}", description.to_line());
+        }
+    }
 }
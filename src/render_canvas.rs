@@ -1,3 +1,7 @@
+use std::io::Write;
+
+use pathfinder_geometry::{rect::RectI, vector::Vector2I};
+
 #[derive(Clone, Copy, Debug)]
 pub struct Color {
     pub r: u8,
@@ -31,17 +35,39 @@ impl Color {
 pub struct RenderCanvas {
     pixels: Vec<Color>,
     width: u32,
-    height: u32
+    height: u32,
+    // the output scale, the width and height above are already multiplied by this
+    scale: f32,
+    // areas changed since the last time the canvas was pushed to wayland, in actual pixels
+    damage: Vec<RectI>
 }
 impl RenderCanvas {
-    pub fn new(width: u32, height: u32) -> Self {
+    pub fn new(width: u32, height: u32, scale: f32) -> Self {
         Self {
             pixels: vec![Color::new(0, 0, 0, 255); (width * height) as usize],
             width,
-            height
+            height,
+            scale,
+            damage: Vec::new()
         }
     }
 
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    // turns a logical (unscaled) length into a length in actual pixels on the canvas
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss, clippy::cast_precision_loss)]
+    pub fn scale_value(&self, value: u32) -> u32 {
+        (value as f32 * self.scale).round() as u32
+    }
+
     pub fn set_pixel(&mut self, x: u32, y: u32, color: Color) {
         if x >= self.width || y >= self.height {
             return;
@@ -56,20 +82,81 @@ impl RenderCanvas {
             }
         }
     }
+    // same as draw_box, but takes logical coordinates and scales them to the canvas.
+    // edges are scaled rather than sizes so that boxes next to each other never leave a gap
+    pub fn draw_scaled_box(&mut self, x: u32, y: u32, w: u32, h: u32, color: Color) {
+        let start_x = self.scale_value(x);
+        let start_y = self.scale_value(y);
+        let end_x = self.scale_value(x + w);
+        let end_y = self.scale_value(y + h);
+        self.draw_box(start_x, start_y, end_x - start_x, end_y - start_y, color);
+    }
     pub fn wipe(&mut self, color: Color) {
-        self.pixels = vec![color; (self.width * self.height) as usize];
+        self.pixels.fill(color);
+        self.damage_all();
+    }
+
+    // marks a logical area as needing to be pushed to wayland, scaled the same as draw_scaled_box
+    pub fn damage_scaled(&mut self, x: u32, y: u32, w: u32, h: u32) {
+        let start_x = self.scale_value(x);
+        let start_y = self.scale_value(y);
+        let end_x = self.scale_value(x + w);
+        let end_y = self.scale_value(y + h);
+        self.damage(start_x, start_y, end_x - start_x, end_y - start_y);
+    }
+    pub fn damage(&mut self, x: u32, y: u32, w: u32, h: u32) {
+        // clamp to the canvas, anything outside of it was never drawn anyway
+        let end_x = (x + w).min(self.width);
+        let end_y = (y + h).min(self.height);
+        if x >= end_x || y >= end_y {
+            return;
+        }
+
+        let to_i32 = |value: u32| i32::try_from(value).expect("damage to i32 failed");
+        let rect = RectI::from_points(Vector2I::new(to_i32(x), to_i32(y)), Vector2I::new(to_i32(end_x), to_i32(end_y)));
+        // no need to keep anything that's already covered
+        if self.damage.iter().any(|damaged| damaged.intersection(rect) == Some(rect)) {
+            return;
+        }
+        self.damage.retain(|damaged| rect.intersection(*damaged) != Some(*damaged));
+        self.damage.push(rect);
+    }
+    pub fn damage_all(&mut self) {
+        self.damage(0, 0, self.width, self.height);
+    }
+    pub fn take_damage(&mut self) -> Vec<RectI> {
+        std::mem::take(&mut self.damage)
+    }
+
+    // copies the given areas into a wayland buffer, the buffer must be the same size as the canvas
+    pub fn fill_wayland_canvas(&self, canvas: &mut [u8], areas: &[RectI]) {
+        let width = self.width as usize;
+        for area in areas {
+            let start_x = usize::try_from(area.min_x()).expect("area x to usize failed");
+            let end_x = usize::try_from(area.max_x()).expect("area max x to usize failed");
+            for y in area.min_y()..area.max_y() {
+                let row = usize::try_from(y).expect("area y to usize failed") * width;
+                let pixels = &self.pixels[row + start_x..row + end_x];
+                let bytes = &mut canvas[(row + start_x) * 4..(row + end_x) * 4];
+                for (chunk, pixel) in bytes.chunks_exact_mut(4).zip(pixels) {
+                    chunk.copy_from_slice(&pixel.get_wayland_color().to_le_bytes());
+                }
+            }
+        }
     }
 
-    pub fn fill_wayland_canvas(&self, canvas: &mut [u8]) {
-        canvas.chunks_exact_mut(4).enumerate().for_each(|(index, chunk)| {
-            let width_usize = usize::try_from(self.width).expect("width to usize failed");
-            let x = u32::try_from(index % width_usize).expect("x to u32 failed");
-            let y = u32::try_from(index / width_usize).expect("y to u32 failed");
+    // the whole canvas as rgba bytes, row by row
+    pub fn to_rgba(&self) -> Vec<u8> {
+        self.pixels.iter().flat_map(|pixel| [pixel.r, pixel.g, pixel.b, pixel.a]).collect()
+    }
 
-            let pixel_index = self.index_from_pixel(x, y);
-            let array: &mut [u8; 4] = chunk.try_into().unwrap();
-            *array = self.pixels[pixel_index].get_wayland_color().to_le_bytes();
-        });
+    // encodes the canvas as a png, for snapshots and screenshots without a compositor
+    pub fn write_png(&self, writer: impl Write) -> Result<(), png::EncodingError> {
+        let mut encoder = png::Encoder::new(writer, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.to_rgba())
     }
 
     fn index_from_pixel(&self, x: u32, y: u32) -> usize {
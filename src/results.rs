@@ -1,7 +1,12 @@
-use std::{collections::HashMap, time::Instant};
+use std::collections::HashMap;
 use freedesktop_desktop_entry::{current_desktop, default_paths, get_languages_from_env, DesktopEntry, Iter};
 
-use crate::sprint_config::SprintConfig;
+use crate::{entry_box::EntryBoxValue, sprint_config::SearchConfig, timing};
+
+// the most results a single provider can give, there's no room to show more anyway
+const ELEMENT_LIMIT: usize = 50;
+// everything result_order can contain
+pub const RESULT_TYPES: [&str; 5] = ["prefixes", "math", "desktop", "dmenu", "search"];
 
 #[derive(Default, Debug)]
 pub struct SprintResults {
@@ -9,8 +14,11 @@ pub struct SprintResults {
     pub math_result: Option<f64>,
     pub desktop_results: Vec<DesktopEntry>,
     pub web_result: (String, String),
+    pub dmenu_results: Vec<String>,
 
     desktop_file_cache: Vec<DesktopEntry>,
+    // items given on stdin in dmenu mode, in the order they were given
+    dmenu_items: Vec<String>,
     desktop_locale_cache: Vec<String>,
     current_desktop: Option<Vec<String>>
 }
@@ -23,27 +31,75 @@ impl SprintResults {
             math_result: None,
             desktop_results: Vec::new(),
             web_result: (String::new(), String::new()),
+            dmenu_results: Vec::new(),
 
-            desktop_file_cache: Iter::new(default_paths())
-                .entries(Some(&locales))
-                .collect::<Vec<_>>(),
+            desktop_file_cache: Self::scan_desktop_entries(&locales),
+            dmenu_items: Vec::new(),
             desktop_locale_cache: locales,
             current_desktop: current_desktop()
         }
     }
 
-    pub fn refresh_results(&mut self, input: &str, config: &SprintConfig) {
-        let time = Instant::now();
+    // picks up applications installed or removed since the last scan, which a daemon needs to do
+    // as it can stay running for as long as the session does
+    pub fn rescan_desktop_entries(&mut self) {
+        self.desktop_file_cache = Self::scan_desktop_entries(&self.desktop_locale_cache);
+    }
+
+    fn scan_desktop_entries(locales: &[String]) -> Vec<DesktopEntry> {
+        let _span = timing::span("Desktop file scan");
+        Iter::new(default_paths())
+            .entries(Some(locales))
+            // a broken entry shouldn't stop the rest from showing
+            .filter(|entry| {
+                let has_name = entry.full_name(locales).is_some();
+                if !has_name {
+                    log::warn!("Skipping desktop entry {} as it has no name", entry.path.display());
+                }
+                has_name
+            })
+            .collect()
+    }
+
+    pub fn set_dmenu_items(&mut self, items: Vec<String>) {
+        self.dmenu_items = items;
+    }
+
+    pub fn refresh_results(&mut self, input: &str, config: &SearchConfig) {
+        let _span = timing::span(format!("Results search for '{input}'"));
 
         self.prefix_results = Self::get_prefix_results(input, config);
         self.math_result = Self::get_math_result(input);
         self.desktop_results = Self::get_desktop_entries(input, &self.desktop_file_cache, &self.desktop_locale_cache, &self.current_desktop);
         self.web_result = Self::get_web_result(input, config);
+        self.dmenu_results = Self::get_dmenu_results(input, &self.dmenu_items);
+    }
 
-        println!("Results search time for '{input}': {:?}", time.elapsed());
+    // every result in the order the config asks for, ready to be shown or printed
+    pub fn ordered_results(&self, config: &SearchConfig) -> Vec<EntryBoxValue> {
+        let mut results = Vec::new();
+        for result_type in &config.result_order {
+            match result_type.to_lowercase().as_str() {
+                "prefixes" => {
+                    if let Some(prefix) = &self.prefix_results {
+                        results.push(EntryBoxValue::WebPrefix(prefix.0.clone(), prefix.1.clone(), prefix.2.clone()));
+                    }
+                },
+                "math" => {
+                    if let Some(math) = self.math_result {
+                        results.push(EntryBoxValue::Math(math));
+                    }
+                },
+                "desktop" => results.extend(self.desktop_results.iter().take(ELEMENT_LIMIT).map(|desktop| EntryBoxValue::Desktop(desktop.to_owned()))),
+                "dmenu" => results.extend(self.dmenu_results.iter().take(ELEMENT_LIMIT).map(|item| EntryBoxValue::Dmenu(item.clone()))),
+                "search" => results.push(EntryBoxValue::WebSearch(self.web_result.0.clone(), self.web_result.1.clone())),
+                _ => log::warn!("Unknown result type {result_type}")
+            }
+        }
+        results
     }
 
-    fn get_prefix_results(input: &str, config: &SprintConfig) -> Option<(String, String, String)> {
+    fn get_prefix_results(input: &str, config: &SearchConfig) -> Option<(String, String, String)> {
         let mut result: Option<(String, String, String)> = None;
         for prefix in &config.web_prefixes {
             if let Some(query) = input.strip_prefix(&prefix.1) {
@@ -68,16 +124,30 @@ impl SprintResults {
         None
     }
 
-    fn get_web_result(input: &str, config: &SprintConfig) -> (String, String) {
+    fn get_web_result(input: &str, config: &SearchConfig) -> (String, String) {
         (input.to_string(), config.search_template.replace("%%QUERY%%", &input.replace(' ', "+")))
     }
 
+    fn get_dmenu_results(input: &str, items: &[String]) -> Vec<String> {
+        // like dmenu, nothing typed shows everything in the order it was given, which scripts
+        // rely on for things like most recent first history
+        if input.is_empty() {
+            return items.to_vec();
+        }
+        let mut results = items.iter()
+            .filter_map(|item| Some((fuzzy_score(input, item)?, item)))
+            .collect::<Vec<_>>();
+        // best match first, the sort is stable so equal matches keep the order they were given in
+        results.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+        results.into_iter().map(|(_, item)| item.clone()).collect()
+    }
+
     #[allow(clippy::ref_option)]
     fn get_desktop_entries(input: &str, desktop_files: &[DesktopEntry], desktop_locales: &[String], current_desktop: &Option<Vec<String>>) -> Vec<DesktopEntry> {
         let mut entries = HashMap::new();
         desktop_files.iter()
             // Name
-            .filter(|entry| entry.full_name(desktop_locales).unwrap().to_lowercase().contains(&input.to_lowercase()))
+            .filter(|entry| entry.full_name(desktop_locales).is_some_and(|name| name.to_lowercase().contains(&input.to_lowercase())))
             // Is it hidden?
             .filter(|entry| !entry.no_display())
             // Only show in these desktops
@@ -108,7 +178,74 @@ impl SprintResults {
             });
 
         let mut entries_vec = entries.values().cloned().collect::<Vec<_>>();
-        entries_vec.sort_unstable_by_key(|item| item.full_name(desktop_locales).expect("Failed to fetch app name from locale.").to_string());
+        entries_vec.sort_unstable_by_key(|item| item.full_name(desktop_locales).unwrap_or_default().to_string());
         entries_vec
     }
 }
+
+// scores how well the query matches the item as a case insensitive subsequence, so "ffx" matches
+// "Firefox". matches at the start of words and runs of matches score higher, gaps score lower.
+// none means the item doesn't match at all
+fn fuzzy_score(query: &str, item: &str) -> Option<i64> {
+    let item = item.chars().flat_map(char::to_lowercase).collect::<Vec<_>>();
+    let mut score: i64 = 0;
+    let mut position = 0;
+    let mut last_match = None;
+
+    for query_char in query.chars().flat_map(char::to_lowercase) {
+        let found = position + item[position..].iter().position(|item_char| *item_char == query_char)?;
+        score += 1;
+        if found == 0 || !item[found - 1].is_alphanumeric() {
+            score += 8;
+        }
+        if found > 0 && last_match == Some(found - 1) {
+            score += 4;
+        }
+        // the first gap is free, otherwise "bar" would never score well against "foobar"
+        if last_match.is_some() {
+            score -= i64::try_from(found - position).expect("gap to i64 failed");
+        }
+
+        last_match = Some(found);
+        position = found + 1;
+    }
+    Some(score)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn items(items: &[&str]) -> Vec<String> {
+        items.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn fuzzy_score_needs_every_char_in_order() {
+        assert!(fuzzy_score("fx", "Firefox").is_some());
+        assert!(fuzzy_score("xf", "Firefox").is_none());
+        assert_eq!(fuzzy_score("", "Firefox"), Some(0));
+    }
+
+    #[test]
+    fn fuzzy_score_prefers_word_starts_and_runs() {
+        let word_start = fuzzy_score("ed", "Editor").expect("ed should match");
+        let mid_word = fuzzy_score("ed", "Speed").expect("ed should match");
+        assert!(word_start > mid_word);
+        let run = fuzzy_score("ab", "xabx").expect("ab should match");
+        let gap = fuzzy_score("ab", "xaxb").expect("ab should match");
+        assert!(run > gap);
+    }
+
+    #[test]
+    fn dmenu_keeps_input_order_with_no_query() {
+        let given = items(&["shutdown", "reboot", "suspend"]);
+        assert_eq!(SprintResults::get_dmenu_results("", &given), given);
+    }
+
+    #[test]
+    fn dmenu_breaks_ties_by_input_order() {
+        let given = items(&["suspend", "reboot", "shutdown", "s"]);
+        assert_eq!(SprintResults::get_dmenu_results("s", &given), items(&["suspend", "shutdown", "s"]));
+    }
+}
@@ -1,28 +1,53 @@
-use std::{env, fs, io::Write, path::{Path, PathBuf}};
+use std::{collections::HashSet, env, fs, io::Write, path::{Path, PathBuf}};
 
-use config::{Config, File};
-use font_kit::{font::Font, source::SystemSource};
-use serde::Deserialize;
+use font_kit::{family_name::FamilyName, font::Font, properties::{Properties, Style, Weight}, source::SystemSource};
+use serde::de::DeserializeOwned;
+use toml::de::{DeTable, ValueDeserializer};
 
-use crate::render_canvas::Color;
+use crate::{error::{ConfigDiagnostic, Result, SprintError}, font::SharedFont, render_canvas::Color, results::RESULT_TYPES, text_label::Truncation, timing};
 
-const DEFAULT_CONFIG_CONTENTS: &str = include_str!("../default-config.toml");
+pub const DEFAULT_CONFIG_CONTENTS: &str = include_str!("../default-config.toml");
+// where the config is within each config directory, the user's and any in XDG_CONFIG_DIRS
+const CONFIG_PATH: &str = "sprint/config.toml";
+const LEGACY_CONFIG_PATH: &str = "sprint.toml";
+// lists other config files to apply before the rest of the file, relative to the file itself
+const INCLUDE_KEY: &str = "include";
+// every key the config can have, each of which can also be set with a SPRINT_<KEY> variable
+const KEYS: [&str; 18] = ["font", "font_weight", "font_style", "monospace_font", "input_font_size", "result_font_size", "truncation", "background_color", "foreground_color", "seperator_color", "selection_hover_color", "search_template", "web_prefixes", "result_order", "output", "placeholder", "width", "height"];
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug)]
 struct SprintConfigRaw {
     font: String,
+    font_weight: Weight,
+    font_style: Style,
+    // the main font is used for math results too unless this is set
+    monospace_font: Option<String>,
+    input_font_size: f32,
+    result_font_size: f32,
+    // none picks for each result, see Entrybox
+    truncation: Option<Truncation>,
     background_color: (u8, u8, u8),
     foreground_color: (u8, u8, u8),
     seperator_color: (u8, u8, u8),
     selection_hover_color: (u8, u8, u8),
     search_template: String,
     web_prefixes: Vec<(String, String, String)>,
-    result_order: Vec<String>
+    result_order: Vec<String>,
+    output: String,
+    placeholder: String,
+    width: u32,
+    height: u32
 }
 impl Default for SprintConfigRaw {
     fn default() -> Self {
         Self {
-            font: "FreeSans".to_string(),
+            font: "sans-serif".to_string(),
+            font_weight: Weight::NORMAL,
+            font_style: Style::Normal,
+            monospace_font: None,
+            input_font_size: 18.0,
+            result_font_size: 16.0,
+            truncation: None,
             background_color: (25, 25, 25),
             foreground_color: (30, 30, 30),
             seperator_color: (112, 69, 156),
@@ -43,92 +68,685 @@ impl Default for SprintConfigRaw {
                 ("Bing".to_string(), ">bing".to_string(), "https://www.bing.com/search?q=%%QUERY%%".to_string()),
                 ("DuckDuckGo".to_string(), ">ddg".to_string(), "https://duckduckgo.com/?q=%%QUERY%%".to_string()),
             ],
-            result_order: vec!["prefixes".to_string(), "math".to_string(), "desktop".to_string(), "search".to_string()]
+            result_order: vec!["prefixes".to_string(), "math".to_string(), "desktop".to_string(), "search".to_string()],
+            output: "focused".to_string(),
+            placeholder: "Search...".to_string(),
+            width: 1024,
+            height: 512
         }
     }
 }
 impl SprintConfigRaw {
-    pub fn load() -> Self {
-        if let Some(config) = SprintConfigRaw::locate_config() {
-            Config::builder()
-                .add_source(File::from(config))
-                .build()
-                .expect("Failed to load configuration.")
-                .try_deserialize::<SprintConfigRaw>()
-                .expect("Failed to deserialize config file.")
+    // layers each source of config over the defaults, the system wide files, then the user's file
+    // or the one given instead, then SPRINT_* environment variables. each only needs to set what
+    // it wants to change, and anything invalid is reported and left as the layers below set it
+    fn load(config_path: Option<&Path>, generate_default: bool) -> ConfigLoader {
+        let mut loader = ConfigLoader::default();
+
+        for directory in system_config_dirs() {
+            loader.watch_paths.extend(config_paths_in(&directory));
+            if let Some(system_path) = config_in(&directory) {
+                loader.apply_file(&system_path);
+            }
+        }
+
+        let user_path = if let Some(config_path) = config_path {
+            Some(config_path.to_path_buf())
         } else {
-            SprintConfigRaw::default()
+            if let Some(config_home) = config_home() {
+                loader.watch_paths.extend(config_paths_in(&config_home));
+            }
+            SprintConfigRaw::locate_config(generate_default).unwrap_or_else(|error| {
+                loader.errors.push(error);
+                None
+            })
+        };
+        if let Some(user_path) = user_path {
+            loader.watch_paths.push(user_path.clone());
+            loader.apply_file(&user_path);
         }
+
+        loader.apply_env();
+        loader
     }
 
-    fn locate_config() -> Option<PathBuf> {
-        if let Ok(mut config_home) = env::var("XDG_CONFIG_HOME") {
-            config_home.push_str("/sprint.toml");
-            let path = PathBuf::from(config_home);
-            if path.exists() {
-                return Some(path);
-            } 
+    // finds the user's config, making the default one if there isn't one yet and we're allowed to
+    fn locate_config(generate_default: bool) -> Result<Option<PathBuf>> {
+        let Some(config_home) = config_home() else {
+            return Ok(None);
+        };
+        if let Some(path) = config_in(&config_home) {
+            return Ok(Some(path));
+        }
+        if !generate_default {
+            return Ok(None);
+        }
 
-            Self::generate_default_config_file(&path);
-            return None;
+        let path = config_home.join(CONFIG_PATH);
+        Self::generate_default_config_file(&path)?;
+        Ok(Some(path))
+    }
+
+    fn generate_default_config_file(path: &Path) -> Result<()> {
+        if path.exists() {
+            return Ok(());
         }
-        if let Ok(mut user_home) = env::var("HOME") {
-            user_home.push_str("/.config/sprint.toml");
-            let path = PathBuf::from(user_home);
-            if path.exists() {
-                return Some(path);
+
+        fs::create_dir_all(path.parent().expect("config path has no parent"))
+            .and_then(|()| fs::File::create(path))
+            .and_then(|mut config_file| config_file.write_all(DEFAULT_CONFIG_CONTENTS.as_bytes()))
+            .map_err(|source| SprintError::ConfigCreate { path: path.to_path_buf(), source })?;
+        log::info!("Created default config file {}", path.display());
+        Ok(())
+    }
+
+    // sets a single key from its raw toml value, returning anything wrong with it. a value that
+    // can't be used at all leaves the key as it was, otherwise only the bad parts are dropped
+    fn apply_key(&mut self, key: &str, raw: &str) -> Vec<String> {
+        let result = match key {
+            "font" => parse_value(raw).map(|font| self.font = font),
+            "font_weight" => parse_weight(raw).map(|font_weight| self.font_weight = font_weight),
+            "font_style" => parse_style(raw).map(|font_style| self.font_style = font_style),
+            "monospace_font" => parse_value(raw).map(|monospace_font| self.monospace_font = Some(monospace_font)),
+            "input_font_size" => parse_font_size(raw).map(|input_font_size| self.input_font_size = input_font_size),
+            "result_font_size" => parse_font_size(raw).map(|result_font_size| self.result_font_size = result_font_size),
+            "truncation" => parse_truncation(raw).map(|truncation| self.truncation = truncation),
+            "background_color" => parse_color(raw).map(|color| self.background_color = color),
+            "foreground_color" => parse_color(raw).map(|color| self.foreground_color = color),
+            "seperator_color" => parse_color(raw).map(|color| self.seperator_color = color),
+            "selection_hover_color" => parse_color(raw).map(|color| self.selection_hover_color = color),
+            "search_template" => parse_value(raw).map(|search_template| self.search_template = search_template),
+            "web_prefixes" => return parse_value(raw).map_or_else(|message| vec![message], |web_prefixes| self.set_web_prefixes(web_prefixes)),
+            "result_order" => return parse_value(raw).map_or_else(|message| vec![message], |result_order| self.set_result_order(result_order)),
+            "output" => parse_value(raw).map(|output| self.output = output),
+            "placeholder" => parse_value(raw).map(|placeholder| self.placeholder = placeholder),
+            "width" => parse_size(raw).map(|width| self.width = width),
+            "height" => parse_size(raw).map(|height| self.height = height),
+            _ => Err("Unknown key, it will be ignored".to_string())
+        };
+        result.err().into_iter().collect()
+    }
+
+    fn set_web_prefixes(&mut self, web_prefixes: Vec<(String, String, String)>) -> Vec<String> {
+        let mut problems = Vec::new();
+        let mut seen = HashSet::new();
+        self.web_prefixes = web_prefixes.into_iter()
+            .filter(|(name, prefix, _)| {
+                // an empty prefix would take over every search
+                if prefix.is_empty() {
+                    problems.push(format!("The prefix for {name} is empty, so it will be ignored"));
+                    return false;
+                }
+                if !seen.insert(prefix.clone()) {
+                    problems.push(format!("The prefix {prefix} for {name} is already used, only the first one is kept"));
+                    return false;
+                }
+                true
+            })
+            .collect();
+        problems
+    }
+
+    fn set_result_order(&mut self, result_order: Vec<String>) -> Vec<String> {
+        let (result_order, problems) = check_result_order(result_order);
+        self.result_order = result_order;
+        problems
+    }
+}
+
+// drops unknown and repeated result types, returning what's left and what was wrong. used for
+// --providers too, so it can't get around this
+pub fn check_result_order(result_order: Vec<String>) -> (Vec<String>, Vec<String>) {
+    let mut problems = Vec::new();
+    let mut seen = HashSet::new();
+    let result_order = result_order.into_iter()
+        .filter(|result_type| {
+            if !RESULT_TYPES.contains(&result_type.to_lowercase().as_str()) {
+                problems.push(format!("Unknown result type {result_type}, expected one of {}", RESULT_TYPES.join(", ")));
+                return false;
             }
+            // the same results twice over is never wanted
+            if !seen.insert(result_type.to_lowercase()) {
+                problems.push(format!("The result type {result_type} is listed more than once"));
+                return false;
+            }
+            true
+        })
+        .collect();
+    (result_order, problems)
+}
 
-            Self::generate_default_config_file(&path);
-            return None;
+// a config being layered together, along with everything that went wrong doing it
+#[derive(Default)]
+struct ConfigLoader {
+    config: SprintConfigRaw,
+    errors: Vec<SprintError>,
+    // every file that was read, or would have been if it existed, so changes to them can be picked up
+    watch_paths: Vec<PathBuf>,
+    // the files being applied, includer first, so a file can't end up including itself
+    including: Vec<PathBuf>
+}
+impl ConfigLoader {
+    fn apply_env(&mut self) {
+        for key in KEYS {
+            let variable = format!("SPRINT_{}", key.to_uppercase());
+            let Ok(value) = env::var(&variable) else {
+                continue;
+            };
+            // values are toml, but quoting plain strings in a shell is a pain so they don't need it
+            let quoted = toml::Value::String(value.clone()).to_string();
+            let raw = if ValueDeserializer::parse(&value).is_ok() { value } else { quoted.clone() };
+            let mut problems = self.config.apply_key(key, &raw);
+            // a string that happens to look like something else, like a placeholder of 2024
+            if !problems.is_empty() && raw != quoted && self.config.apply_key(key, &quoted).is_empty() {
+                problems.clear();
+            }
+            for message in problems {
+                self.errors.push(SprintError::InvalidEnvConfig { variable: variable.clone(), message });
+            }
         }
+    }
 
-        None
+    // sets whatever the file sets, leaving anything invalid as it was. anything it includes is
+    // applied first, so the file itself can override what it pulls in
+    fn apply_file(&mut self, path: &Path) {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(source) => {
+                self.errors.push(SprintError::ConfigRead { path: path.to_path_buf(), source });
+                return;
+            }
+        };
+        let diagnostic = |offset: usize, key: Option<&str>, message: String| SprintError::InvalidConfig(ConfigDiagnostic {
+            path: path.to_path_buf(),
+            line: contents[..offset].matches('\n').count() + 1,
+            key: key.map(str::to_string),
+            message
+        });
+
+        let table = match DeTable::parse(&contents) {
+            Ok(table) => table.into_inner(),
+            Err(error) => {
+                self.errors.push(diagnostic(error.span().map_or(0, |span| span.start), None, error.message().to_string()));
+                return;
+            }
+        };
+        // the table is sorted by key, but problems read better in the order they're in the file
+        let mut entries = table.iter().collect::<Vec<_>>();
+        entries.sort_by_key(|(key, _)| key.span().start);
+
+        let canonical_path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        self.including.push(canonical_path);
+        if let Some((key, value)) = entries.iter().find(|(key, _)| key.get_ref() == INCLUDE_KEY) {
+            match parse_value::<Vec<PathBuf>>(&contents[value.span()]) {
+                Ok(includes) => {
+                    let directory = path.parent().unwrap_or(Path::new(""));
+                    for include in includes {
+                        if let Some(message) = self.include(&directory.join(include)) {
+                            self.errors.push(diagnostic(key.span().start, Some(INCLUDE_KEY), message));
+                        }
+                    }
+                },
+                Err(message) => self.errors.push(diagnostic(key.span().start, Some(INCLUDE_KEY), message))
+            }
+        }
+        self.including.pop();
+
+        for (key, value) in entries {
+            if key.get_ref() == INCLUDE_KEY {
+                continue;
+            }
+            let raw = &contents[value.span()];
+            for message in self.config.apply_key(key.get_ref(), raw) {
+                self.errors.push(diagnostic(key.span().start, Some(key.get_ref()), message));
+            }
+        }
     }
 
-    fn generate_default_config_file(path: &Path) {
-        if path.exists() {
-            return;
+    // applies an included file, returning what's wrong with including it if it can't be
+    fn include(&mut self, path: &Path) -> Option<String> {
+        self.watch_paths.push(path.to_path_buf());
+        if !path.exists() {
+            return Some(format!("{} doesn't exist", path.display()));
+        }
+        let canonical_path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        if self.including.contains(&canonical_path) {
+            return Some(format!("{} is already being included, so including it again would never end", path.display()));
         }
+        self.apply_file(path);
+        None
+    }
+}
+
+// the user's config directory
+fn config_home() -> Option<PathBuf> {
+    env::var("XDG_CONFIG_HOME").ok().filter(|config_home| !config_home.is_empty()).map(PathBuf::from)
+        .or_else(|| env::var("HOME").ok().map(|user_home| PathBuf::from(user_home).join(".config")))
+}
+
+// the system's config directories, least important first so they can be layered in order
+fn system_config_dirs() -> Vec<PathBuf> {
+    let config_dirs = env::var("XDG_CONFIG_DIRS").ok().filter(|config_dirs| !config_dirs.is_empty()).unwrap_or_else(|| "/etc/xdg".to_string());
+    config_dirs.split(':')
+        .filter(|directory| !directory.is_empty())
+        .rev()
+        .map(PathBuf::from)
+        .collect()
+}
+
+// where sprint's config can be in a config directory. the first is preferred, the second is
+// where older versions of sprint put it
+fn config_paths_in(directory: &Path) -> [PathBuf; 2] {
+    [directory.join(CONFIG_PATH), directory.join(LEGACY_CONFIG_PATH)]
+}
+
+fn config_in(directory: &Path) -> Option<PathBuf> {
+    config_paths_in(directory).into_iter().find(|path| path.exists())
+}
 
-        let mut config_file = fs::File::create(path).expect("Unable to create default config file.");
-        config_file.write_all(DEFAULT_CONFIG_CONTENTS.as_bytes()).expect("Unable to write to default config file.");
-        println!("Created default config file");
+// the parts of the config searching needs, which can be loaded without anything else
+#[derive(Clone, Debug)]
+pub struct SearchConfig {
+    pub search_template: String,
+    pub web_prefixes: Vec<(String, String, String)>,
+    pub result_order: Vec<String>
+}
+impl SearchConfig {
+    // loads the config the same way as SprintConfig, but without looking up any fonts or making
+    // the default config file, so it works from scripts and over ssh without touching $HOME
+    pub fn load(config_path: Option<&Path>) -> (Self, Vec<SprintError>) {
+        let _span = timing::span("Search config load");
+        let ConfigLoader { config: raw_config, errors, .. } = SprintConfigRaw::load(config_path, false);
+        (Self::from_raw(&raw_config), errors)
+    }
+
+    fn from_raw(raw_config: &SprintConfigRaw) -> Self {
+        Self {
+            search_template: raw_config.search_template.clone(),
+            web_prefixes: raw_config.web_prefixes.clone(),
+            result_order: raw_config.result_order.clone()
+        }
     }
 }
 
 #[derive(Clone, Debug)]
 pub struct SprintConfig {
-    // TODO: Currently fonts have to be cloned due to it not impling copy, is there a way around
-    // this? Got close with Cow's but tainting every struct with a lifetime seems
-    // counter-productive
-    pub font: Font,
+    // shared rather than copied into every label, so cloning them is cheap
+    pub font: SharedFont,
+    // used for math results, where lining digits up matters
+    pub monospace_font: SharedFont,
+    pub input_font_size: f32,
+    pub result_font_size: f32,
+    // how results too long to fit are cut short, none to pick for each result
+    pub truncation: Option<Truncation>,
     pub background_color: Color,
     pub foreground_color: Color,
     pub seperator_color: Color,
     pub selection_hover_color: Color,
-    pub search_template: String,
-    pub web_prefixes: Vec<(String, String, String)>,
-    pub result_order: Vec<String>
+    pub search: SearchConfig,
+    pub output: String,
+    pub placeholder: String,
+    pub width: u32,
+    pub height: u32,
+    // every file the config was read from, or would have been if it existed, for noticing changes
+    pub watch_paths: Vec<PathBuf>
 }
 impl SprintConfig {
-    pub fn load() -> Self {
-        let raw_config = SprintConfigRaw::load();
+    // loads the config from the given path, or the default location if there isn't one. a config
+    // or font that fails to load falls back to the defaults, and what went wrong is returned
+    // alongside so it can be shown. only having no font at all to fall back on is an error
+    pub fn load(config_path: Option<&Path>) -> Result<(Self, Vec<SprintError>)> {
+        let _span = timing::span("Config load");
+        let ConfigLoader { config: raw_config, mut errors, watch_paths, .. } = SprintConfigRaw::load(config_path, true);
+
+        // Load the fonts
+        let properties = *Properties::new().weight(raw_config.font_weight).style(raw_config.font_style);
+        let font = match load_font(&raw_config.font, &properties) {
+            Ok(font) => font,
+            Err(error) => {
+                errors.push(error);
+                load_fallback_font(&properties)?
+            }
+        };
+        let monospace_font = raw_config.monospace_font.as_ref().and_then(|name| {
+            load_font(name, &properties).map_err(|error| errors.push(error)).ok()
+        });
+
+        let mut config = Self::from_raw(raw_config, SharedFont::new(font), watch_paths);
+        if let Some(monospace_font) = monospace_font {
+            config.monospace_font = SharedFont::new(monospace_font);
+        }
+        Ok((config, errors))
+    }
 
-        // Load the font
-        let font_source = SystemSource::new();
-        let font_handle = font_source.select_by_postscript_name(&raw_config.font).expect("Failed to find font for configuration.");
-        let font = font_handle.load().expect("Failed to load font.");
+    // the default config, but with the given font rather than looking one up on the system
+    pub fn with_font(font: Font) -> Self {
+        Self::from_raw(SprintConfigRaw::default(), SharedFont::new(font), Vec::new())
+    }
 
+    fn from_raw(raw_config: SprintConfigRaw, font: SharedFont, watch_paths: Vec<PathBuf>) -> Self {
         Self {
+            monospace_font: font.clone(),
             font,
+            input_font_size: raw_config.input_font_size,
+            result_font_size: raw_config.result_font_size,
+            truncation: raw_config.truncation,
             background_color: Color::from_tuple(raw_config.background_color, 255),
             foreground_color: Color::from_tuple(raw_config.foreground_color, 255),
             seperator_color: Color::from_tuple(raw_config.seperator_color, 255),
             selection_hover_color: Color::from_tuple(raw_config.selection_hover_color, 255),
-            search_template: raw_config.search_template.to_string(),
-            web_prefixes: raw_config.web_prefixes,
-            result_order: raw_config.result_order
+            search: SearchConfig::from_raw(&raw_config),
+            output: raw_config.output,
+            placeholder: raw_config.placeholder,
+            width: raw_config.width,
+            height: raw_config.height,
+            watch_paths
+        }
+    }
+}
+
+fn parse_value<T: DeserializeOwned>(raw: &str) -> std::result::Result<T, String> {
+    ValueDeserializer::parse(raw)
+        .and_then(T::deserialize)
+        .map_err(|error| error.message().to_string())
+}
+
+fn parse_color(raw: &str) -> std::result::Result<(u8, u8, u8), String> {
+    let invalid = || format!("Expected a colour as [red, green, blue] from 0 to 255, not {raw}");
+    let channels = parse_value::<Vec<i64>>(raw).map_err(|_| invalid())?;
+    let [red, green, blue] = channels[..] else {
+        return Err(invalid());
+    };
+    let channel = |value: i64| u8::try_from(value).map_err(|_| invalid());
+    Ok((channel(red)?, channel(green)?, channel(blue)?))
+}
+
+// anything smaller doesn't leave room for the search box and a result
+fn parse_size(raw: &str) -> std::result::Result<u32, String> {
+    let size = parse_value::<u32>(raw)?;
+    if size < MIN_SIZE {
+        return Err(format!("Must be at least {MIN_SIZE}, not {size}"));
+    }
+    Ok(size)
+}
+const MIN_SIZE: u32 = 100;
+
+// anything smaller can't be read, and the search box and rows aren't tall enough for anything bigger
+fn parse_font_size(raw: &str) -> std::result::Result<f32, String> {
+    let size = parse_value::<f32>(raw)?;
+    if !(MIN_FONT_SIZE..=MAX_FONT_SIZE).contains(&size) {
+        return Err(format!("Must be between {MIN_FONT_SIZE} and {MAX_FONT_SIZE}, not {size}"));
+    }
+    Ok(size)
+}
+const MIN_FONT_SIZE: f32 = 6.0;
+const MAX_FONT_SIZE: f32 = 24.0;
+
+// either a css style weight from 1 to 1000, or one of the names font-kit has for them
+fn parse_weight(raw: &str) -> std::result::Result<Weight, String> {
+    let invalid = || format!("Expected a weight from 1 to 1000, or one of {}, not {raw}", WEIGHT_NAMES.map(|(name, _)| name).join(", "));
+    if let Ok(weight) = parse_value::<u16>(raw) {
+        return if (1..=1000).contains(&weight) { Ok(Weight(f32::from(weight))) } else { Err(invalid()) };
+    }
+    let name = parse_value::<String>(raw).map_err(|_| invalid())?;
+    WEIGHT_NAMES.iter()
+        .find(|(weight_name, _)| weight_name.eq_ignore_ascii_case(&name))
+        .map(|(_, weight)| *weight)
+        .ok_or_else(invalid)
+}
+const WEIGHT_NAMES: [(&str, Weight); 9] = [("thin", Weight::THIN), ("extra_light", Weight::EXTRA_LIGHT), ("light", Weight::LIGHT), ("normal", Weight::NORMAL), ("medium", Weight::MEDIUM), ("semibold", Weight::SEMIBOLD), ("bold", Weight::BOLD), ("extra_bold", Weight::EXTRA_BOLD), ("black", Weight::BLACK)];
+
+fn parse_truncation(raw: &str) -> std::result::Result<Option<Truncation>, String> {
+    match parse_value::<String>(raw)?.to_lowercase().as_str() {
+        "auto" => Ok(None),
+        "end" => Ok(Some(Truncation::End)),
+        "middle" => Ok(Some(Truncation::Middle)),
+        "clip" => Ok(Some(Truncation::Clip)),
+        _ => Err(format!("Expected one of auto, end, middle or clip, not {raw}"))
+    }
+}
+
+fn parse_style(raw: &str) -> std::result::Result<Style, String> {
+    match parse_value::<String>(raw)?.to_lowercase().as_str() {
+        "normal" => Ok(Style::Normal),
+        "italic" => Ok(Style::Italic),
+        "oblique" => Ok(Style::Oblique),
+        _ => Err(format!("Expected one of normal, italic or oblique, not {raw}"))
+    }
+}
+
+// looks the font up by family, so a family name with a weight and style picks the right face.
+// postscript names like FreeSans-Bold still work as they did before
+fn load_font(name: &str, properties: &Properties) -> Result<Font> {
+    let family = match name.to_lowercase().as_str() {
+        "sans-serif" | "sans" => FamilyName::SansSerif,
+        "serif" => FamilyName::Serif,
+        "monospace" | "mono" => FamilyName::Monospace,
+        _ => FamilyName::Title(name.to_string())
+    };
+    let source = SystemSource::new();
+    let font_handle = source.select_best_match(&[family], properties)
+        .or_else(|error| source.select_by_postscript_name(name).map_err(|_| error))
+        .map_err(|source| SprintError::FontSelection { name: name.to_string(), source })?;
+    Ok(font_handle.load()?)
+}
+
+// whatever sans serif font the system has, for when the configured one can't be used
+fn load_fallback_font(properties: &Properties) -> Result<Font> {
+    load_font("sans-serif", properties)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    // the environment is shared by every test thread, so tests that change it take turns
+    static ENVIRONMENT: Mutex<()> = Mutex::new(());
+
+    // a directory of its own for each test, with the given files written into it
+    fn config_dir(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let directory = env::temp_dir().join(format!("sprint-config-test-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        for (path, contents) in files {
+            let path = directory.join(path);
+            fs::create_dir_all(path.parent().expect("test file has no parent")).expect("Failed to create test config dir.");
+            fs::write(path, contents).expect("Failed to write test config.");
+        }
+        directory
+    }
+
+    fn load_file(path: &Path) -> ConfigLoader {
+        let mut loader = ConfigLoader::default();
+        loader.apply_file(path);
+        loader
+    }
+
+    fn diagnostics(loader: &ConfigLoader) -> Vec<&ConfigDiagnostic> {
+        loader.errors.iter()
+            .map(|error| match error {
+                SprintError::InvalidConfig(diagnostic) => diagnostic,
+                error => panic!("Expected a config diagnostic, not {error}")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn partial_config_is_merged_over_the_defaults() {
+        let directory = config_dir("partial", &[("config.toml", "width = 800\nplaceholder = \"Go...\"\n")]);
+        let loader = load_file(&directory.join("config.toml"));
+        let defaults = SprintConfigRaw::default();
+        assert!(loader.errors.is_empty());
+        assert_eq!(loader.config.width, 800);
+        assert_eq!(loader.config.placeholder, "Go...");
+        assert_eq!(loader.config.height, defaults.height);
+        assert_eq!(loader.config.result_order, defaults.result_order);
+    }
+
+    #[test]
+    fn env_overrides_files() {
+        let directory = config_dir("env", &[("config.toml", "width = 800\nplaceholder = \"Go...\"\n")]);
+        let mut loader = load_file(&directory.join("config.toml"));
+        let _environment = ENVIRONMENT.lock().expect("Environment lock poisoned.");
+        // SAFETY: every test that touches the environment holds its lock
+        unsafe {
+            env::set_var("SPRINT_WIDTH", "900");
+            // looks like a number, but is wanted as a string
+            env::set_var("SPRINT_PLACEHOLDER", "2024");
+        }
+        loader.apply_env();
+        // SAFETY: as above
+        unsafe {
+            env::remove_var("SPRINT_WIDTH");
+            env::remove_var("SPRINT_PLACEHOLDER");
+        }
+        assert!(loader.errors.is_empty());
+        assert_eq!(loader.config.width, 900);
+        assert_eq!(loader.config.placeholder, "2024");
+    }
+
+    #[test]
+    fn duplicate_prefix_is_dropped_with_a_diagnostic() {
+        let contents = "web_prefixes = [\n    [\"One\", \">a\", \"https://one/%%QUERY%%\"],\n    [\"Two\", \">a\", \"https://two/%%QUERY%%\"],\n]\n";
+        let directory = config_dir("duplicate_prefix", &[("config.toml", contents)]);
+        let loader = load_file(&directory.join("config.toml"));
+        assert_eq!(loader.config.web_prefixes, vec![("One".to_string(), ">a".to_string(), "https://one/%%QUERY%%".to_string())]);
+        let diagnostics = diagnostics(&loader);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].key.as_deref(), Some("web_prefixes"));
+        assert_eq!(diagnostics[0].line, 1);
+    }
+
+    #[test]
+    fn duplicate_result_type_is_dropped_with_a_diagnostic() {
+        let directory = config_dir("duplicate_result_type", &[("config.toml", "result_order = [\"math\", \"dmenu\", \"Math\"]\n")]);
+        let loader = load_file(&directory.join("config.toml"));
+        assert_eq!(loader.config.result_order, vec!["math".to_string(), "dmenu".to_string()]);
+        assert_eq!(diagnostics(&loader).len(), 1);
+    }
+
+    #[test]
+    fn diagnostics_report_the_line_of_the_key() {
+        let contents = "# a comment\nwidth = 800\n\nheight = 5\nbackground_color = [1, 2]\nnot_a_key = true\n";
+        let directory = config_dir("lines", &[("config.toml", contents)]);
+        let loader = load_file(&directory.join("config.toml"));
+        let lines = diagnostics(&loader).iter().map(|diagnostic| (diagnostic.line, diagnostic.key.clone().unwrap_or_default())).collect::<Vec<_>>();
+        assert_eq!(lines, vec![(4, "height".to_string()), (5, "background_color".to_string()), (6, "not_a_key".to_string())]);
+        // the valid key is still applied, the invalid ones are left as they were
+        assert_eq!(loader.config.width, 800);
+        assert_eq!(loader.config.height, SprintConfigRaw::default().height);
+    }
+
+    #[test]
+    fn syntax_errors_report_their_line() {
+        let directory = config_dir("syntax", &[("config.toml", "width = 800\nheight = \n")]);
+        let loader = load_file(&directory.join("config.toml"));
+        let diagnostics = diagnostics(&loader);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].line, 2);
+        assert_eq!(diagnostics[0].key, None);
+    }
+
+    #[test]
+    fn includes_are_applied_before_the_including_file() {
+        let directory = config_dir("include", &[
+            ("config.toml", "include = [\"themes/dark.toml\"]\nwidth = 800\n"),
+            ("themes/dark.toml", "width = 600\nheight = 300\n")
+        ]);
+        let loader = load_file(&directory.join("config.toml"));
+        assert!(loader.errors.is_empty());
+        assert_eq!(loader.config.width, 800);
+        assert_eq!(loader.config.height, 300);
+        assert!(loader.watch_paths.contains(&directory.join("themes/dark.toml")));
+    }
+
+    #[test]
+    fn include_cycle_is_reported_rather_than_followed() {
+        let directory = config_dir("include_cycle", &[
+            ("a.toml", "include = [\"b.toml\"]\nwidth = 800\n"),
+            ("b.toml", "include = [\"a.toml\"]\nheight = 300\n")
+        ]);
+        let loader = load_file(&directory.join("a.toml"));
+        assert_eq!(loader.config.width, 800);
+        assert_eq!(loader.config.height, 300);
+        let diagnostics = diagnostics(&loader);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].path, directory.join("b.toml"));
+        assert_eq!(diagnostics[0].key.as_deref(), Some(INCLUDE_KEY));
+        assert!(diagnostics[0].message.contains("already being included"));
+    }
+
+    #[test]
+    fn missing_include_is_reported() {
+        let directory = config_dir("include_missing", &[("config.toml", "width = 800\ninclude = [\"nowhere.toml\"]\n")]);
+        let loader = load_file(&directory.join("config.toml"));
+        let diagnostics = diagnostics(&loader);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].line, 2);
+        assert_eq!(loader.config.width, 800);
+    }
+
+    #[test]
+    fn system_config_dirs_are_layered_least_important_first() {
+        let _environment = ENVIRONMENT.lock().expect("Environment lock poisoned.");
+        // SAFETY: every test that touches the environment holds its lock
+        unsafe { env::set_var("XDG_CONFIG_DIRS", "/etc/first::/etc/second") };
+        let directories = system_config_dirs();
+        // SAFETY: as above
+        unsafe { env::remove_var("XDG_CONFIG_DIRS") };
+        assert_eq!(directories, vec![PathBuf::from("/etc/second"), PathBuf::from("/etc/first")]);
+        assert_eq!(system_config_dirs(), vec![PathBuf::from("/etc/xdg")]);
+    }
+
+    #[test]
+    fn config_is_found_in_the_new_path_before_the_legacy_one() {
+        let legacy = config_dir("legacy", &[(LEGACY_CONFIG_PATH, "")]);
+        assert_eq!(config_in(&legacy), Some(legacy.join(LEGACY_CONFIG_PATH)));
+        let both = config_dir("both", &[(LEGACY_CONFIG_PATH, ""), (CONFIG_PATH, "")]);
+        assert_eq!(config_in(&both), Some(both.join(CONFIG_PATH)));
+        let neither = config_dir("neither", &[]);
+        assert_eq!(config_in(&neither), None);
+    }
+
+    #[test]
+    fn generated_config_leaves_system_values_alone() {
+        let directory = config_dir("generated", &[("system/sprint/config.toml", "width = 700
+")]);
+        let _environment = ENVIRONMENT.lock().expect("Environment lock poisoned.");
+        // SAFETY: every test that touches the environment holds its lock
+        unsafe {
+            env::set_var("XDG_CONFIG_DIRS", directory.join("system"));
+            env::set_var("XDG_CONFIG_HOME", directory.join("home"));
+        }
+        let loader = SprintConfigRaw::load(None, true);
+        // SAFETY: as above
+        unsafe {
+            env::remove_var("XDG_CONFIG_DIRS");
+            env::remove_var("XDG_CONFIG_HOME");
+        }
+        assert!(directory.join("home").join(CONFIG_PATH).exists());
+        assert!(loader.errors.is_empty());
+        assert_eq!(loader.config.width, 700);
+    }
+
+    #[test]
+    fn search_config_doesnt_make_the_config_file() {
+        let directory = config_dir("search", &[("system/sprint/config.toml", "result_order = [\"math\"]\n")]);
+        let _environment = ENVIRONMENT.lock().expect("Environment lock poisoned.");
+        // SAFETY: every test that touches the environment holds its lock
+        unsafe {
+            env::set_var("XDG_CONFIG_DIRS", directory.join("system"));
+            env::set_var("XDG_CONFIG_HOME", directory.join("home"));
+        }
+        let (search, errors) = SearchConfig::load(None);
+        // SAFETY: as above
+        unsafe {
+            env::remove_var("XDG_CONFIG_DIRS");
+            env::remove_var("XDG_CONFIG_HOME");
         }
+        assert!(!directory.join("home").exists());
+        assert!(errors.is_empty());
+        assert_eq!(search.result_order, vec!["math".to_string()]);
     }
 }
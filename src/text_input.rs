@@ -0,0 +1,112 @@
+use pathfinder_geometry::rect::RectI;
+use smithay_client_toolkit::reexports::protocols::wp::text_input::zv3::client::zwp_text_input_v3::{ChangeCause, ContentHint, ContentPurpose, ZwpTextInputV3};
+
+use crate::launcher::Launcher;
+
+// talks to the input method through text-input-v3, so text composed by fcitx5, ibus and the like
+// can be typed. the protocol double buffers everything both ways, so changes are gathered here
+// until they're done, and what we tell it only applies once committed
+pub struct TextInput {
+    input: ZwpTextInputV3,
+    // the input method only cares about us while our surface has focus
+    enabled: bool,
+    // how many times we've committed, which the input method echoes back so we know it's caught up
+    commits: u32,
+    pending: PendingChanges,
+    // what the input method was last told, so it's only told again when something has moved
+    sent: Option<(String, usize, RectI)>
+}
+
+// what the input method has sent since it was last done
+#[derive(Default)]
+struct PendingChanges {
+    preedit: Option<(String, Option<usize>)>,
+    commit: Option<String>,
+    delete: Option<(usize, usize)>
+}
+
+impl TextInput {
+    pub fn new(text_input: ZwpTextInputV3) -> Self {
+        Self {
+            input: text_input,
+            enabled: false,
+            commits: 0,
+            pending: PendingChanges::default(),
+            sent: None
+        }
+    }
+
+    pub fn enable(&mut self, launcher: &Launcher) {
+        self.enabled = true;
+        self.sent = None;
+        self.input.enable();
+        self.input.set_content_type(ContentHint::None, ContentPurpose::Normal);
+        self.update(launcher, ChangeCause::Other);
+    }
+    pub fn disable(&mut self, launcher: &mut Launcher) {
+        self.enabled = false;
+        self.pending = PendingChanges::default();
+        self.input.disable();
+        self.commit();
+        // anything still being composed goes with the focus
+        launcher.set_preedit("", None);
+    }
+
+    // the preedit's cursor is in bytes, with negatives meaning it's hidden
+    pub fn preedit_string(&mut self, text: Option<String>, cursor_begin: i32) {
+        self.pending.preedit = Some((text.unwrap_or_default(), usize::try_from(cursor_begin).ok()));
+    }
+    pub fn commit_string(&mut self, text: Option<String>) {
+        self.pending.commit = text;
+    }
+    pub fn delete_surrounding_text(&mut self, before_length: u32, after_length: u32) {
+        self.pending.delete = Some((before_length as usize, after_length as usize));
+    }
+
+    // applies everything sent since last time, in the order the protocol sets out
+    pub fn done(&mut self, serial: u32, launcher: &mut Launcher) {
+        let pending = std::mem::take(&mut self.pending);
+        launcher.set_preedit("", None);
+        if let Some((before, after)) = pending.delete {
+            launcher.delete_around_cursor(before, after);
+        }
+        if let Some(commit) = pending.commit {
+            launcher.insert_text(&commit);
+        }
+        if let Some((preedit, cursor)) = pending.preedit {
+            launcher.set_preedit(&preedit, cursor);
+        }
+        // the input method has moved on since this was sent, and will send more to catch up
+        if serial == self.commits {
+            self.update(launcher, ChangeCause::InputMethod);
+        }
+    }
+
+    // tells the input method where the text and cursor are, if they've changed since we last did
+    pub fn update(&mut self, launcher: &Launcher, cause: ChangeCause) {
+        if !self.enabled {
+            return;
+        }
+        let (text, cursor) = launcher.surrounding_text();
+        let cursor_rect = launcher.cursor_rect();
+        if self.sent.as_ref().is_some_and(|sent| sent.0 == text && sent.1 == cursor && sent.2 == cursor_rect) {
+            return;
+        }
+        let cursor_int = i32::try_from(cursor).expect("cursor to i32 failed");
+        self.input.set_surrounding_text(text.to_string(), cursor_int, cursor_int);
+        self.input.set_text_change_cause(cause);
+        self.input.set_cursor_rectangle(cursor_rect.origin_x(), cursor_rect.origin_y(), cursor_rect.width(), cursor_rect.height());
+        self.sent = Some((text.to_string(), cursor, cursor_rect));
+        self.commit();
+    }
+
+    fn commit(&mut self) {
+        self.input.commit();
+        self.commits = self.commits.wrapping_add(1);
+    }
+}
+impl Drop for TextInput {
+    fn drop(&mut self) {
+        self.input.destroy();
+    }
+}
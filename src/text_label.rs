@@ -1,107 +1,454 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::{LazyLock, Mutex}};
 
-use font_kit::{canvas::{Canvas, Format, RasterizationOptions}, font::Font, hinting::HintingOptions};
-use pathfinder_geometry::{rect::RectI, transform2d::Transform2F, vector::{Vector2F, Vector2I}};
+use pathfinder_geometry::{rect::RectI, vector::{Vector2F, Vector2I}};
+use unicode_bidi::BidiInfo;
 
-use crate::render_canvas::{CanvasRenderable, Color};
+use crate::{font::{self, RasterizedGlyph, SharedFont}, render_canvas::{CanvasRenderable, Color, RenderCanvas}};
 
 #[derive(Debug)]
 pub struct TextLabel {
     position: Vector2I,
     size: Vector2I,
-    font_canvas: Option<Canvas>,
     text: String,
-    font: Font,
+    font: SharedFont,
     font_size: f32,
-    requires_rerender: bool,
-    character_length_cache: HashMap<char, RectI>
+    truncation: Truncation,
+    // how far the text is scrolled left, in actual pixels, for text that's wider than the label
+    scroll: u32,
+    // where each glyph is drawn, in actual pixels from the label's position
+    glyphs: Vec<PositionedGlyph>,
+    // how wide each char of the text is, in the order they're stored rather than shown
+    advances: Vec<f32>,
+    // where the cursor goes before each char of the text and after the last, in actual pixels.
+    // right to left text means these aren't always increasing
+    carets: Vec<f32>,
+    // the scale and atlas generation the glyphs were last laid out for
+    scale: f32,
+    atlas_generation: u64,
+    requires_layout: bool
+}
+
+// what to do with text that's too wide for the label
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Truncation {
+    // cut it off at the edge, for text that's scrolled instead
+    Clip,
+    // replace the end with an ellipsis
+    End,
+    // replace the middle with an ellipsis, for urls and paths where the end matters as much
+    Middle
+}
+
+#[derive(Clone, Copy, Debug)]
+struct PositionedGlyph {
+    glyph: AtlasGlyph,
+    pen: Vector2I
 }
 
 impl TextLabel {
-    pub fn new(text: &str, font: Font, font_size: f32, position: Vector2I, size: Vector2I) -> Self {
+    pub fn new(text: &str, font: SharedFont, font_size: f32, position: Vector2I, size: Vector2I) -> Self {
         Self {
             position,
             size,
-            font_canvas: None,
-            requires_rerender: true, // triggers the first render
             text: text.to_string(),
             font,
             font_size,
-            character_length_cache: HashMap::new()
+            truncation: Truncation::End,
+            scroll: 0,
+            glyphs: Vec::new(),
+            advances: Vec::new(),
+            carets: Vec::new(),
+            scale: 1.0,
+            atlas_generation: 0,
+            requires_layout: true // triggers the first layout
         }
     }
-    
+
     pub fn set_text(&mut self, text: &str) {
         self.text = text.to_string();
-        self.requires_rerender = true;
+        self.requires_layout = true;
+    }
+    pub fn set_truncation(&mut self, truncation: Truncation) {
+        self.truncation = truncation;
+        self.requires_layout = true;
+    }
+    pub fn set_scroll(&mut self, scroll: u32) {
+        self.scroll = scroll;
+    }
+
+    // how far into the text the given char is, in actual pixels at the given scale
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn find_cursor_length(&mut self, place: usize, scale: f32) -> u32 {
+        self.ensure_layout(scale);
+        let caret = self.carets.get(place).or(self.carets.last()).copied().unwrap_or_default();
+        caret.round() as u32
+    }
+
+    // how wide the text is, in actual pixels at the given scale
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn text_width(&mut self, scale: f32) -> u32 {
+        self.ensure_layout(scale);
+        self.advances.iter().sum::<f32>().round() as u32
+    }
+
+    // the place of the cursor next to the given one on screen, so the cursor moves through right
+    // to left text in the order it's shown rather than the order it's stored
+    pub fn visual_neighbour(&mut self, place: usize, right: bool) -> usize {
+        self.ensure_layout(self.scale);
+        let Some(&x) = self.carets.get(place) else {
+            return place;
+        };
+        self.carets.iter().enumerate()
+            .filter(|(_, caret)| if right { **caret > x } else { **caret < x })
+            .min_by(|(a_place, a), (b_place, b)| (*a - x).abs().total_cmp(&(*b - x).abs()).then(a_place.abs_diff(place).cmp(&b_place.abs_diff(place))))
+            .map_or(place, |(place, _)| place)
+    }
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+    fn scaled_vector(vector: Vector2I, scale: f32) -> Vector2I {
+        (vector.to_f32() * scale).round().to_i32()
     }
 
-    pub fn find_cursor_length(&self, place: usize) -> u32 {
-        let mut length: u32 = 0;
-        self.text[0..place].chars()
-            .for_each(|char| length += u32::try_from(self.character_length_cache[&char].width()).expect("char width to u32 failed"));
-        length
+    fn ensure_layout(&mut self, scale: f32) {
+        let mut atlas = GLYPH_ATLAS.lock().expect("glyph atlas lock failed");
+        self.ensure_layout_in(scale, &mut atlas);
     }
 
+    // takes the atlas from the caller, so drawing can keep hold of it between laying out and
+    // reading the glyphs back rather than letting another thread clear it in between
+    fn ensure_layout_in(&mut self, scale: f32, atlas: &mut GlyphAtlas) {
+        #[allow(clippy::float_cmp)]
+        if self.requires_layout || self.scale != scale || self.atlas_generation != atlas.generation {
+            self.layout(scale, atlas);
+            // the atlas filled up and was cleared part way through, so the first glyphs are gone
+            if self.atlas_generation != atlas.generation {
+                self.layout(scale, atlas);
+            }
+        }
+    }
+
+    // works out which glyphs go where, truncating the text if it doesn't fit
     #[allow(clippy::cast_precision_loss)]
-    fn rasterize_to_font_canvas(&mut self) {
-        if !self.requires_rerender {
-            return;
+    fn layout(&mut self, scale: f32, atlas: &mut GlyphAtlas) {
+        self.scale = scale;
+        self.atlas_generation = atlas.generation;
+        let text = std::mem::take(&mut self.text);
+        self.layout_text(&text, atlas);
+
+        let available = Self::scaled_vector(self.size, scale).x() as f32;
+        let width = self.advances.iter().sum::<f32>();
+        if self.truncation != Truncation::Clip && width > available {
+            // the advances are for the whole text until it's laid out again truncated
+            let advances = std::mem::take(&mut self.advances);
+            let ellipsis = if self.font.glyph_for_char('…').is_some() { "…" } else { "..." };
+            self.layout_text(ellipsis, atlas);
+            let ellipsis_width = self.advances.iter().sum::<f32>();
+            let truncated = truncate(&text, &advances, available - ellipsis_width, self.truncation, ellipsis);
+            self.layout_text(&truncated, atlas);
         }
+        self.text = text;
+        self.requires_layout = false;
+    }
 
-        self.font_canvas = Some(Canvas::new(self.size, Format::A8));
+    // lays the text out in the order it's shown, which is only different to the order it's stored
+    // when there's right to left text in it
+    #[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation)]
+    fn layout_text(&mut self, text: &str, atlas: &mut GlyphAtlas) {
+        let size = Self::scaled_vector(self.size, self.scale);
+        let font_size = self.font_size * self.scale;
+        let space_width = 8.0 * self.scale;
+        self.glyphs.clear();
 
-        self.character_length_cache = HashMap::new();
-        let canvas = self.font_canvas.as_mut().unwrap();
+        let char_count = text.chars().count();
+        // where each char starts and if it's right to left, in the order they're stored
+        let mut starts = vec![0.0; char_count];
+        let mut right_to_left = vec![false; char_count];
+        self.advances = vec![0.0; char_count];
 
-        let mut transform = Transform2F::from_translation(Vector2F::new(0.0, self.size.y() as f32 / 1.5));
-        for char in self.text.chars() {
+        let mut pen = Vector2F::new(0.0, size.y() as f32 / 1.5);
+        for (index, char, rtl) in visual_order(text) {
+            starts[index] = pen.x();
+            right_to_left[index] = rtl;
             if char.is_whitespace() {
-                // transform and move on 
-                transform = transform.translate(Vector2F::new(8.0, 0.0));
-                self.character_length_cache.entry(' ').or_insert(RectI::new(Vector2I::new(0, 0), Vector2I::new(8, 0)));
+                // move on without drawing anything
+                pen += Vector2F::new(space_width, 0.0);
+                self.advances[index] = space_width;
                 continue;
             }
 
-            let mut glyph_id = 0; // unknown glyph
-            if let Some(found_id) = self.font.glyph_for_char(char) {
-                glyph_id = found_id;
-            }
-            // find the bounds so we can transform the next char correctly
-            // BUG: this fails with some characters, i believe this is a font kit issue?
-            // https://github.com/servo/font-kit/issues/253
-            let bounds = self.font.raster_bounds(glyph_id, self.font_size, transform, HintingOptions::None, RasterizationOptions::GrayscaleAa).unwrap();
-            self.character_length_cache.entry(char).or_insert(bounds);
-            // actually render it to the canvas
-            self.font.rasterize_glyph(canvas, glyph_id, self.font_size, transform, HintingOptions::None, RasterizationOptions::GrayscaleAa).unwrap();
-            // adjust the transform
-            transform = transform.translate(Vector2F::new(bounds.width() as f32, 0.0));
+            // brackets and the like point the other way in right to left text
+            let char = if rtl { mirrored(char) } else { char };
+            let glyph_id = self.font.glyph_for_char(char).unwrap_or(0); // 0 is the unknown glyph
+            let whole_pen = pen.floor();
+            let Some(glyph) = atlas.glyph(&self.font, glyph_id, font_size, pen - whole_pen) else {
+                continue;
+            };
+            self.glyphs.push(PositionedGlyph { glyph, pen: whole_pen.to_i32() });
+            // the bounds tell us how far along the next char goes
+            pen += Vector2F::new(glyph.bounds.width() as f32, 0.0);
+            self.advances[index] = glyph.bounds.width() as f32;
         }
-        self.requires_rerender = false;
+
+        // the cursor sits on the edge of the char after it that comes first in reading order, the
+        // left for left to right text and the right for right to left. at the very end it goes on
+        // the far edge of the last char instead
+        let leading_edge = |index: usize| if right_to_left[index] { starts[index] + self.advances[index] } else { starts[index] };
+        let trailing_edge = |index: usize| if right_to_left[index] { starts[index] } else { starts[index] + self.advances[index] };
+        self.carets = (0..char_count).map(leading_edge)
+            .chain(std::iter::once(char_count.checked_sub(1).map_or(0.0, trailing_edge)))
+            .collect();
     }
 }
 impl CanvasRenderable for TextLabel {
-    fn draw(&mut self, canvas: &mut crate::render_canvas::RenderCanvas) {
-        // canvas.draw_box(self.position.x() as u32, self.position.y() as u32, self.size.x() as u32, self.size.y() as u32, Color::new(255, 0, 0, 255));
-        self.rasterize_to_font_canvas();
-        if self.font_canvas.is_none() {
-            return;
-        }
+    fn draw(&mut self, canvas: &mut RenderCanvas) {
+        let mut atlas = GLYPH_ATLAS.lock().expect("glyph atlas lock failed");
+        self.ensure_layout_in(canvas.scale(), &mut atlas);
 
-        let font_canvas = self.font_canvas.as_ref().unwrap();
-        for y in 0..self.size.y() {
-            for x in 0..self.size.x() {
-                let final_x: u32 = u32::try_from(x + self.position.x()).expect("failed to make final x to u32");
-                let final_y: u32 = u32::try_from(y + self.position.y()).expect("failed to make final x to u32");
-
-                let row = font_canvas.stride * usize::try_from(y).expect("y to usize failed");
-                let pixel_index = row + (font_canvas.format.bytes_per_pixel() as usize * usize::try_from(x).expect("x to usize failed"));
-                let color = font_canvas.pixels[pixel_index];
-                if color < 60 {
-                    continue;
+        let position = Self::scaled_vector(self.position, self.scale);
+        let label_rect = RectI::new(position, Self::scaled_vector(self.size, self.scale));
+        let scroll = Vector2I::new(i32::try_from(self.scroll).expect("scroll to i32 failed"), 0);
+        let mut previous: Option<(RectI, &AtlasGlyph)> = None;
+        for positioned in &self.glyphs {
+            let glyph_rect = RectI::new(positioned.glyph.coverage_rect.origin() + position + positioned.pen - scroll, positioned.glyph.coverage_rect.size());
+            // anything outside of the label is cut off, truncated text never gets this far
+            let Some(visible_rect) = glyph_rect.intersection(label_rect) else {
+                continue;
+            };
+            for y in visible_rect.min_y().max(0)..visible_rect.max_y() {
+                for x in visible_rect.min_x().max(0)..visible_rect.max_x() {
+                    let point = Vector2I::new(x, y);
+                    let mut coverage = atlas.coverage(&positioned.glyph, point - glyph_rect.origin());
+                    // neighbouring glyphs can overlap a little, where the more covered one wins
+                    if let Some((previous_rect, previous_glyph)) = previous && previous_rect.contains_point(point) {
+                        coverage = coverage.max(atlas.coverage(previous_glyph, point - previous_rect.origin()));
+                    }
+                    if coverage < 60 {
+                        continue;
+                    }
+                    let final_x = u32::try_from(x).expect("failed to make final x to u32");
+                    let final_y = u32::try_from(y).expect("failed to make final y to u32");
+                    canvas.set_pixel(final_x, final_y, Color::new_mono(coverage, 255));
                 }
-                canvas.set_pixel(final_x, final_y, Color::new_mono(color, 255));
+            }
+            previous = Some((glyph_rect, &positioned.glyph));
+        }
+    }
+}
+
+// cuts the text down to fit in the width with the ellipsis added, given how wide each char is
+fn truncate(text: &str, advances: &[f32], width: f32, truncation: Truncation, ellipsis: &str) -> String {
+    let chars = text.chars().collect::<Vec<_>>();
+    // how many chars fit in the width, taking them in the given order
+    let fitting = |advances: &mut dyn Iterator<Item = &f32>, width: f32| {
+        let mut total = 0.0;
+        advances.take_while(|advance| {
+            total += **advance;
+            total <= width
+        }).count()
+    };
+    match truncation {
+        Truncation::Clip => text.to_string(),
+        Truncation::End => {
+            let start = chars[..fitting(&mut advances.iter(), width)].iter().collect::<String>();
+            format!("{}{ellipsis}", start.trim_end())
+        },
+        Truncation::Middle => {
+            let start_count = fitting(&mut advances.iter(), width / 2.0);
+            let start_width = advances[..start_count].iter().sum::<f32>();
+            // then as many from the end as fit in what's left
+            let end_count = fitting(&mut advances[start_count..].iter().rev(), width - start_width);
+            let start = chars[..start_count].iter().collect::<String>();
+            let end = chars[chars.len() - end_count..].iter().collect::<String>();
+            format!("{}{ellipsis}{}", start.trim_end(), end.trim_start())
+        }
+    }
+}
+
+// the chars of the text in the order they're shown, along with where they are in the text and if
+// they're right to left
+fn visual_order(text: &str) -> Vec<(usize, char, bool)> {
+    let bidi = BidiInfo::new(text, None);
+    if !bidi.has_rtl() {
+        return text.chars().enumerate().map(|(index, char)| (index, char, false)).collect();
+    }
+
+    let char_starts = text.char_indices().map(|(byte, _)| byte).collect::<Vec<_>>();
+    let char_index = |byte: usize| char_starts.binary_search(&byte).expect("run doesn't start on a char");
+    let mut chars = Vec::with_capacity(char_starts.len());
+    for paragraph in &bidi.paragraphs {
+        let (levels, runs) = bidi.visual_runs(paragraph, paragraph.range.clone());
+        for run in runs {
+            let rtl = levels[run.start].is_rtl();
+            let run_chars = text[run.clone()].char_indices().map(|(offset, char)| (char_index(run.start + offset), char, rtl));
+            if rtl {
+                chars.extend(run_chars.rev());
+            } else {
+                chars.extend(run_chars);
             }
         }
     }
+    chars
+}
+
+fn mirrored(char: char) -> char {
+    match char {
+        '(' => ')',
+        ')' => '(',
+        '[' => ']',
+        ']' => '[',
+        '{' => '}',
+        '}' => '{',
+        '<' => '>',
+        '>' => '<',
+        '«' => '»',
+        '»' => '«',
+        _ => char
+    }
+}
+
+// every glyph drawn so far, packed in rows into one coverage texture shared by every label.
+// labels only keep where their glyphs are in it, so rebuilding them on every keystroke doesn't
+// rasterize or allocate anything for glyphs that have been drawn before
+static GLYPH_ATLAS: LazyLock<Mutex<GlyphAtlas>> = LazyLock::new(|| Mutex::new(GlyphAtlas::new()));
+const ATLAS_SIZE: i32 = 1024;
+
+// where a glyph is in the atlas, and how to place it relative to the pen
+#[derive(Clone, Copy, Debug)]
+struct AtlasGlyph {
+    // the glyph's raster bounds, which the pen advances by
+    bounds: RectI,
+    // where the coverage goes relative to the pen, and where it is in the atlas
+    coverage_rect: RectI,
+    atlas_origin: Vector2I
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct GlyphKey {
+    font_id: usize,
+    glyph_id: u32,
+    // f32 bits, as floats can't be hashed
+    font_size: u32,
+    // only the fraction of a pixel the pen sits at changes how the glyph rasterizes, whole pixels
+    // just move it
+    subpixel_offset: (u32, u32)
+}
+
+struct GlyphAtlas {
+    coverage: Vec<u8>,
+    // where the next glyph goes, and how tall the tallest glyph in the current row is
+    cursor: Vector2I,
+    row_height: i32,
+    // none for glyphs font-kit can't rasterize, so they're only complained about once
+    glyphs: HashMap<GlyphKey, Option<AtlasGlyph>>,
+    // bumped whenever the atlas fills up and is cleared, so labels know to lay themselves out again
+    generation: u64
+}
+impl GlyphAtlas {
+    fn new() -> Self {
+        Self {
+            coverage: vec![0; usize::try_from(ATLAS_SIZE * ATLAS_SIZE).expect("atlas size to usize failed")],
+            cursor: Vector2I::zero(),
+            row_height: 0,
+            glyphs: HashMap::new(),
+            generation: 0
+        }
+    }
+
+    // the glyph at the given size, with the pen the given fraction of a pixel into a pixel
+    fn glyph(&mut self, font: &SharedFont, glyph_id: u32, font_size: f32, subpixel_offset: Vector2F) -> Option<AtlasGlyph> {
+        let key = GlyphKey {
+            font_id: font.id(),
+            glyph_id,
+            font_size: font_size.to_bits(),
+            subpixel_offset: (subpixel_offset.x().to_bits(), subpixel_offset.y().to_bits())
+        };
+        if let Some(glyph) = self.glyphs.get(&key) {
+            return *glyph;
+        }
+        let glyph = font::rasterize_glyph(font, glyph_id, font_size, subpixel_offset).and_then(|glyph| self.insert(&glyph));
+        self.glyphs.insert(key, glyph);
+        glyph
+    }
+
+    fn insert(&mut self, glyph: &RasterizedGlyph) -> Option<AtlasGlyph> {
+        let size = glyph.coverage_rect.size();
+        if size.x() > ATLAS_SIZE || size.y() > ATLAS_SIZE {
+            log::warn!("Glyph is too big to draw at {}x{}", size.x(), size.y());
+            return None;
+        }
+        if self.cursor.x() + size.x() > ATLAS_SIZE {
+            self.cursor = Vector2I::new(0, self.cursor.y() + self.row_height);
+            self.row_height = 0;
+        }
+        // stale fonts from config reloads and old scales would otherwise fill it up for good
+        if self.cursor.y() + size.y() > ATLAS_SIZE {
+            log::debug!("Glyph atlas is full, clearing it");
+            self.glyphs.clear();
+            self.cursor = Vector2I::zero();
+            self.row_height = 0;
+            self.generation += 1;
+        }
+
+        let width = usize::try_from(size.x()).expect("glyph width to usize failed");
+        for (row, coverage_row) in glyph.coverage.chunks_exact(width).enumerate() {
+            let start = Self::index(self.cursor + Vector2I::new(0, i32::try_from(row).expect("glyph row to i32 failed")));
+            self.coverage[start..start + width].copy_from_slice(coverage_row);
+        }
+        let atlas_glyph = AtlasGlyph {
+            bounds: glyph.bounds,
+            coverage_rect: glyph.coverage_rect,
+            atlas_origin: self.cursor
+        };
+        self.cursor += Vector2I::new(size.x(), 0);
+        self.row_height = self.row_height.max(size.y());
+        Some(atlas_glyph)
+    }
+
+    // the coverage of a point in the glyph, relative to the top left of its coverage rect
+    fn coverage(&self, glyph: &AtlasGlyph, point: Vector2I) -> u8 {
+        self.coverage[Self::index(glyph.atlas_origin + point)]
+    }
+
+    fn index(point: Vector2I) -> usize {
+        usize::try_from(point.y() * ATLAS_SIZE + point.x()).expect("atlas index to usize failed")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // every char the same width, so it's easy to see how many should fit
+    fn truncate_uniform(text: &str, width: f32, truncation: Truncation) -> String {
+        truncate(text, &vec![10.0; text.chars().count()], width, truncation, "…")
+    }
+
+    #[test]
+    fn chars_that_fit_exactly_are_kept() {
+        assert_eq!(truncate_uniform("abcdef", 20.0, Truncation::End), "ab…");
+        assert_eq!(truncate_uniform("abcdef", 19.0, Truncation::End), "a…");
+    }
+
+    #[test]
+    fn end_doesnt_leave_a_space_before_the_ellipsis() {
+        assert_eq!(truncate_uniform("ab cdef", 30.0, Truncation::End), "ab…");
+    }
+
+    #[test]
+    fn width_smaller_than_the_ellipsis_leaves_only_the_ellipsis() {
+        assert_eq!(truncate_uniform("abcdef", -5.0, Truncation::End), "…");
+        assert_eq!(truncate_uniform("abcdef", -5.0, Truncation::Middle), "…");
+    }
+
+    #[test]
+    fn middle_keeps_both_ends() {
+        // an even number of chars fitting splits evenly, an odd number gives the spare one to the end
+        assert_eq!(truncate_uniform("abcdef", 40.0, Truncation::Middle), "ab…ef");
+        assert_eq!(truncate_uniform("abcdefg", 50.0, Truncation::Middle), "ab…efg");
+    }
+
+    #[test]
+    fn clip_leaves_the_text_alone() {
+        assert_eq!(truncate_uniform("abcdef", 20.0, Truncation::Clip), "abcdef");
+    }
 }
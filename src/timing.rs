@@ -0,0 +1,19 @@
+use std::time::Instant;
+
+// logs how long something took once it's dropped, so a whole scope can be timed with one line
+pub struct Span {
+    name: String,
+    start: Instant
+}
+impl Drop for Span {
+    fn drop(&mut self) {
+        log::debug!(target: "sprint::timing", "{} took {:?}", self.name, self.start.elapsed());
+    }
+}
+
+pub fn span(name: impl Into<String>) -> Span {
+    Span {
+        name: name.into(),
+        start: Instant::now()
+    }
+}
@@ -1,55 +1,102 @@
-use std::{cmp::min, num::NonZeroU32, time::Instant};
+use std::{num::NonZeroU32, time::Duration};
 
-use pathfinder_geometry::vector::Vector2I;
-use smithay_client_toolkit::{compositor::{CompositorHandler, CompositorState}, delegate_compositor, delegate_keyboard, delegate_layer, delegate_output, delegate_registry, delegate_seat, delegate_shm, output::{OutputHandler, OutputState}, registry::{ProvidesRegistryState, RegistryState}, registry_handlers, seat::{keyboard::{KeyboardHandler, Keysym, RepeatInfo}, Capability, SeatHandler, SeatState}, shell::{wlr_layer::{KeyboardInteractivity, Layer, LayerShell, LayerShellHandler, LayerSurface}, WaylandSurface}, shm::{slot::SlotPool, Shm, ShmHandler}};
-use wayland_client::{globals::registry_queue_init, protocol::{wl_keyboard::WlKeyboard, wl_shm}, Connection, QueueHandle};
+use pathfinder_geometry::{rect::RectI, vector::Vector2I};
+use smithay_client_toolkit::{compositor::{CompositorHandler, CompositorState}, delegate_compositor, delegate_keyboard, delegate_layer, delegate_output, delegate_registry, delegate_seat, delegate_shm, output::{OutputHandler, OutputState}, reexports::{calloop::{generic::Generic, timer::{TimeoutAction, Timer}, EventLoop, Interest, LoopHandle, Mode, PostAction, RegistrationToken}, calloop_wayland_source::WaylandSource, protocols::wp::{fractional_scale::v1::client::{wp_fractional_scale_manager_v1::WpFractionalScaleManagerV1, wp_fractional_scale_v1::{self, WpFractionalScaleV1}}, viewporter::client::{wp_viewport::WpViewport, wp_viewporter::WpViewporter}, text_input::zv3::client::{zwp_text_input_manager_v3::ZwpTextInputManagerV3, zwp_text_input_v3::{self, ChangeCause, ZwpTextInputV3}}}}, registry::{ProvidesRegistryState, RegistryState}, registry_handlers, seat::{keyboard::{KeyboardHandler, Keysym, RepeatInfo}, Capability, SeatHandler, SeatState}, shell::{wlr_layer::{KeyboardInteractivity, Layer, LayerShell, LayerShellHandler, LayerSurface}, WaylandSurface}, shm::{slot::{Buffer, SlotPool}, Shm, ShmHandler}};
+use wayland_client::{delegate_noop, globals::registry_queue_init, protocol::{wl_keyboard::WlKeyboard, wl_output::WlOutput, wl_shm}, Connection, Dispatch, QueueHandle};
 
-use crate::{entry_box::{EntryBoxValue, Entrybox}, input_box::InputBox, render_canvas::{CanvasRenderable, Color, RenderCanvas}, results::SprintResults, sprint_config::SprintConfig, text_label::TextLabel};
-
-// the key to repeat -> the time it was pressed/last repeated -> if it is already repeating or
-// is waiting for delay
-struct RepeatKeyInfo(Keysym, Instant, bool);
+use crate::{config_watch::ConfigWatcher, daemon, entry_box::{EntryBoxValue, Entrybox}, error::{Result, SprintError}, launcher::Launcher, results::SprintResults, sprint_config::SprintConfig, text_input::TextInput, timing};
 
+#[allow(clippy::struct_excessive_bools)]
 struct LayerState {
     registry_state: RegistryState,
     seat_state: SeatState,
     output_state: OutputState,
+    compositor: CompositorState,
+    layer_shell: LayerShell,
+    fractional_scale_manager: Option<WpFractionalScaleManagerV1>,
+    viewporter: Option<WpViewporter>,
+    // only present if the compositor supports input methods, which text is then taken from too
+    text_input_manager: Option<ZwpTextInputManagerV3>,
     shm: Shm,
     close: bool,
+    // if we're closing because something was picked, rather than being dismissed
+    made_selection: bool,
+    options: LaunchOptions,
     first_config: bool,
     pool: SlotPool,
+    // if we're waiting on the compositor to tell us it's a good time to draw again
+    frame_pending: bool,
+    // the last buffer we drew to, reused when the compositor is done with it
+    buffer: Option<Buffer>,
     width: u32,
     height: u32,
-    layer: LayerSurface,
+    // created once we know which output to put it on
+    layer: Option<LayerSurface>,
     keyboard: Option<WlKeyboard>,
-    canvas: RenderCanvas,
-    repeat_key: Option<RepeatKeyInfo>,
+    text_input: Option<TextInput>,
+    // the raw keycode of the key being held down, and the timer repeating it. the keysym can
+    // change while it's held, like when shift is let go of first, but the keycode can't
+    repeat_key: Option<(u32, RegistrationToken)>,
     repeat_delay: Option<u32>,
     repeat_rate: Option<NonZeroU32>,
-
-    // App Data
-    config: SprintConfig,
-    filter: String,
-    filter_results: SprintResults,
-    selected: u8,
-
-    // Components
-    filter_input: InputBox,
-    filter_results_cache: Vec<Entrybox>,
-    no_results_label: TextLabel
+    // handle to the event loop, for key repeat and anything else that needs timers or fd sources
+    loop_handle: LoopHandle<'static, LayerState>,
+    // width and height above are logical, the canvas and buffers are this much bigger
+    scale: f32,
+    // only present if the compositor supports fractional scaling, in which case integer scale
+    // changes are ignored and the viewport maps our bigger buffer back to the logical size
+    fractional_scale: Option<WpFractionalScaleV1>,
+    viewport: Option<WpViewport>,
+
+    launcher: Launcher
 }
 
 impl CompositorHandler for LayerState {
     fn frame(&mut self, _conn: &wayland_client::Connection, qh: &wayland_client::QueueHandle<Self>, _surface: &wayland_client::protocol::wl_surface::WlSurface, _time: u32) {
-        self.draw(qh);
+        self.frame_pending = false;
+        self.draw_if_needed(qh);
     }
 
-    fn scale_factor_changed(&mut self, _conn: &wayland_client::Connection, _qh: &wayland_client::QueueHandle<Self>, _surface: &wayland_client::protocol::wl_surface::WlSurface, _new_factor: i32) {}
+    #[allow(clippy::cast_precision_loss)]
+    fn scale_factor_changed(&mut self, _conn: &wayland_client::Connection, qh: &wayland_client::QueueHandle<Self>, surface: &wayland_client::protocol::wl_surface::WlSurface, new_factor: i32) {
+        if self.fractional_scale.is_some() {
+            return;
+        }
+        surface.set_buffer_scale(new_factor);
+        self.set_scale(new_factor as f32, qh);
+    }
     fn transform_changed(&mut self, _conn: &wayland_client::Connection, _qh: &wayland_client::QueueHandle<Self>, _surface: &wayland_client::protocol::wl_surface::WlSurface, _new_transform: wayland_client::protocol::wl_output::Transform) {}
     fn surface_enter(&mut self, _conn: &wayland_client::Connection, _qh: &wayland_client::QueueHandle<Self>, _surface: &wayland_client::protocol::wl_surface::WlSurface, _output: &wayland_client::protocol::wl_output::WlOutput) {}
     fn surface_leave(&mut self, _conn: &wayland_client::Connection, _qh: &wayland_client::QueueHandle<Self>, _surface: &wayland_client::protocol::wl_surface::WlSurface, _output: &wayland_client::protocol::wl_output::WlOutput) {}
 }
 
+impl Dispatch<WpFractionalScaleV1, ()> for LayerState {
+    #[allow(clippy::cast_precision_loss)]
+    fn event(state: &mut Self, _proxy: &WpFractionalScaleV1, event: wp_fractional_scale_v1::Event, _data: &(), _conn: &Connection, qh: &QueueHandle<Self>) {
+        if let wp_fractional_scale_v1::Event::PreferredScale { scale } = event {
+            // the scale is sent as a numerator over 120
+            state.set_scale(scale as f32 / 120.0, qh);
+        }
+    }
+}
+
+impl Dispatch<ZwpTextInputV3, ()> for LayerState {
+    fn event(state: &mut Self, _proxy: &ZwpTextInputV3, event: zwp_text_input_v3::Event, _data: &(), _conn: &Connection, _qh: &QueueHandle<Self>) {
+        let Some(text_input) = &mut state.text_input else {
+            return;
+        };
+        match event {
+            zwp_text_input_v3::Event::Enter { surface } if state.layer.as_ref().is_some_and(|layer| *layer.wl_surface() == surface) => text_input.enable(&state.launcher),
+            zwp_text_input_v3::Event::Leave { .. } => text_input.disable(&mut state.launcher),
+            zwp_text_input_v3::Event::PreeditString { text, cursor_begin, .. } => text_input.preedit_string(text, cursor_begin),
+            zwp_text_input_v3::Event::CommitString { text } => text_input.commit_string(text),
+            zwp_text_input_v3::Event::DeleteSurroundingText { before_length, after_length } => text_input.delete_surrounding_text(before_length, after_length),
+            zwp_text_input_v3::Event::Done { serial } => text_input.done(serial, &mut state.launcher),
+            _ => {}
+        }
+    }
+}
+
 impl OutputHandler for LayerState {
     fn output_state(&mut self) -> &mut OutputState {
         &mut self.output_state
@@ -62,16 +109,22 @@ impl OutputHandler for LayerState {
 
 impl LayerShellHandler for LayerState {
     fn closed(&mut self, _conn: &wayland_client::Connection, _qh: &QueueHandle<Self>, _layer: &LayerSurface) {
-        self.close = true;
+        self.dismiss();
     }
 
     fn configure(&mut self, _conn: &wayland_client::Connection, qh: &QueueHandle<Self>, _layer: &LayerSurface, configure: smithay_client_toolkit::shell::wlr_layer::LayerSurfaceConfigure, _serial: u32) {
-        self.width = configure.new_size.0;
-        self.height = configure.new_size.1;
+        if configure.new_size != (self.width, self.height) {
+            self.width = configure.new_size.0;
+            self.height = configure.new_size.1;
+            if let Some(viewport) = &self.viewport {
+                viewport.set_destination(i32::try_from(self.width).expect("width to i32 failed"), i32::try_from(self.height).expect("height to i32 failed"));
+            }
+            self.launcher.resize(self.width, self.height, self.scale);
+        }
 
         if self.first_config {
             self.first_config = false;
-            self.draw(qh);
+            self.draw_if_needed(qh);
         }
     }
 }
@@ -83,14 +136,20 @@ impl SeatHandler for LayerState {
 
     fn new_capability(&mut self, _conn: &wayland_client::Connection, qh: &QueueHandle<Self>, seat: wayland_client::protocol::wl_seat::WlSeat, capability: smithay_client_toolkit::seat::Capability) {
         if capability == Capability::Keyboard && self.keyboard.is_none() {
-            let keyboard = self.seat_state.get_keyboard(qh, &seat, None).expect("Failed to create keyboard");
-            self.keyboard = Some(keyboard);
+            match self.seat_state.get_keyboard(qh, &seat, None) {
+                Ok(keyboard) => self.keyboard = Some(keyboard),
+                Err(error) => log::error!("Failed to create keyboard: {error}")
+            }
+            if let Some(manager) = &self.text_input_manager {
+                self.text_input = Some(TextInput::new(manager.get_text_input(&seat, qh, ())));
+            }
         }
     }
 
     fn remove_capability(&mut self, _conn: &wayland_client::Connection, _qh: &QueueHandle<Self>, _seat: wayland_client::protocol::wl_seat::WlSeat, capability: smithay_client_toolkit::seat::Capability) {
         if capability == Capability::Keyboard && self.keyboard.is_some() {
             self.keyboard.take().unwrap().release();
+            self.text_input = None;
         }
     }
 
@@ -100,10 +159,18 @@ impl SeatHandler for LayerState {
 
 impl KeyboardHandler for LayerState {
     fn press_key(&mut self, _conn: &wayland_client::Connection, _qh: &QueueHandle<Self>, _keyboard: &wayland_client::protocol::wl_keyboard::WlKeyboard, _serial: u32, event: smithay_client_toolkit::seat::keyboard::KeyEvent) {
-        if self.repeat_delay.is_some() {
-            self.repeat_key = Some(RepeatKeyInfo(event.keysym, Instant::now(), false));
+        self.stop_key_repeat();
+        // the text comes through xkb's compose table, so it's none part way through a compose or
+        // dead key sequence and the composed text at the end of one
+        self.key_press_handle(event.keysym, event.utf8.as_deref());
+        // a key that closed or hid us won't be released on a surface we still have, so the
+        // repeat would never stop and would press it again on the hidden launcher
+        if self.layer.is_none() || self.close {
+            return;
+        }
+        if let (Some(delay), Some(rate)) = (self.repeat_delay, self.repeat_rate) {
+            self.start_key_repeat(event.raw_code, event.keysym, event.utf8, delay, rate);
         }
-        self.key_press_handle(event.keysym);
     }
 
     fn update_repeat_info(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, _keyboard: &wayland_client::protocol::wl_keyboard::WlKeyboard, info: RepeatInfo) {
@@ -119,16 +186,17 @@ impl KeyboardHandler for LayerState {
         }
     }
     fn release_key(&mut self, _conn: &wayland_client::Connection, _qh: &QueueHandle<Self>, _keyboard: &wayland_client::protocol::wl_keyboard::WlKeyboard, _serial: u32, event: smithay_client_toolkit::seat::keyboard::KeyEvent) {
-        if let Some(RepeatKeyInfo(key, _, _)) = self.repeat_key {
-            if event.keysym == key {
-                self.repeat_key = None;
-            }
+        if self.repeat_key.is_some_and(|(raw_code, _)| raw_code == event.raw_code) {
+            self.stop_key_repeat();
         }
     }
 
     fn update_modifiers(&mut self, _conn: &wayland_client::Connection, _qh: &QueueHandle<Self>, _keyboard: &wayland_client::protocol::wl_keyboard::WlKeyboard, _serial: u32, _modifiers: smithay_client_toolkit::seat::keyboard::Modifiers, _layout: u32) {}
     fn enter(&mut self, _conn: &wayland_client::Connection, _qh: &QueueHandle<Self>, _keyboard: &wayland_client::protocol::wl_keyboard::WlKeyboard, _surface: &wayland_client::protocol::wl_surface::WlSurface, _serial: u32, _raw: &[u32], _keysyms: &[smithay_client_toolkit::seat::keyboard::Keysym]) {}
-    fn leave(&mut self, _conn: &wayland_client::Connection, _qh: &QueueHandle<Self>, _keyboard: &wayland_client::protocol::wl_keyboard::WlKeyboard, _surface: &wayland_client::protocol::wl_surface::WlSurface, _serial: u32) {}
+    fn leave(&mut self, _conn: &wayland_client::Connection, _qh: &QueueHandle<Self>, _keyboard: &wayland_client::protocol::wl_keyboard::WlKeyboard, _surface: &wayland_client::protocol::wl_surface::WlSurface, _serial: u32) {
+        // we won't get the release if we lose focus
+        self.stop_key_repeat();
+    }
 }
 
 impl ShmHandler for LayerState {
@@ -145,124 +213,283 @@ impl ProvidesRegistryState for LayerState {
 }
 
 impl LayerState {
+    // draws only if something has changed, and only once the compositor is ready for a new frame
+    pub fn draw_if_needed(&mut self, qh: &QueueHandle<Self>) {
+        if self.first_config || self.frame_pending || !self.launcher.needs_redraw() {
+            return;
+        }
+        self.draw(qh);
+    }
+
     pub fn draw(&mut self, qh: &QueueHandle<Self>) {
-        let width = self.width;
-        let height = self.height;
-        let width_int = i32::try_from(width).expect("selected height to i32 failed");
-        let height_int = i32::try_from(height).expect("height to i32 failed");
+        if self.layer.is_none() {
+            return;
+        }
+
+        self.launcher.draw();
+        self.push_to_surface(qh);
+        // the cursor may have moved, and the input method's popup with it
+        if let Some(text_input) = &mut self.text_input {
+            text_input.update(&self.launcher, ChangeCause::Other);
+        }
+    }
+
+    // copies whatever changed on the canvas into a wayland buffer and commits it
+    fn push_to_surface(&mut self, qh: &QueueHandle<Self>) {
+        let Some(layer) = &self.layer else {
+            return;
+        };
+        let _span = timing::span("Push to surface");
+
+        // the buffer is in actual pixels, which may be bigger than our logical size
+        let width_int = i32::try_from(self.launcher.canvas().width()).expect("width to i32 failed");
+        let height_int = i32::try_from(self.launcher.canvas().height()).expect("height to i32 failed");
         let stride = width_int * 4;
+        let mut damage = self.launcher.canvas_mut().take_damage();
+
+        // we can only get away with copying the damaged areas if we can reuse the buffer we last
+        // drew to, otherwise the compositor still has it and we need a fresh one we copy fully
+        let reuse_buffer = self.buffer.as_ref().is_some_and(|buffer| {
+            buffer.height() == height_int && buffer.stride() == stride && buffer.canvas(&mut self.pool).is_some()
+        });
+        if !reuse_buffer {
+            let (buffer, _) = self.pool.create_buffer(width_int, height_int, stride, wl_shm::Format::Argb8888).expect("Failed to create buffer on draw.");
+            self.buffer = Some(buffer);
+            damage = vec![RectI::new(Vector2I::zero(), Vector2I::new(width_int, height_int))];
+        }
+
+        // nothing changed, so there's nothing to commit and no frame to wait on
+        if damage.is_empty() {
+            return;
+        }
 
-        let (buffer, canvas) = self.pool.create_buffer(width_int, height_int, stride, wl_shm::Format::Argb8888).expect("Failed to create buffer on draw.");
+        let buffer = self.buffer.as_ref().expect("buffer was not created");
+        let canvas = buffer.canvas(&mut self.pool).expect("Failed to get canvas of buffer.");
+        self.launcher.canvas().fill_wayland_canvas(canvas, &damage);
 
-        self.canvas.wipe(self.config.background_color);
+        for area in &damage {
+            layer.wl_surface().damage_buffer(area.origin_x(), area.origin_y(), area.width(), area.height());
+        }
+        buffer.attach_to(layer.wl_surface()).expect("Failed to attach to buffer");
+        layer.wl_surface().frame(qh, layer.wl_surface().clone());
+        layer.commit();
+        self.frame_pending = true;
+    }
 
-        // Call your component draw calls here, in order you want them to display
-        self.canvas.draw_box(0, 0, 1024, 48, self.config.foreground_color);
-        self.filter_input.draw(&mut self.canvas);
+    fn create_surface(&mut self, qh: &QueueHandle<Self>) {
+        let width_int = i32::try_from(self.width).expect("width to i32 failed");
+        let height_int = i32::try_from(self.height).expect("height to i32 failed");
 
-        let selected_height = HEIGHT_PER_ELEMENT * i32::from(self.selected);
-        if self.filter_results_cache.is_empty() {
-            self.no_results_label.draw(&mut self.canvas);
-        } else {
-            self.canvas.draw_box(0, 49 + u32::try_from(selected_height).expect("selected height to u32 failed"), 1024, HEIGHT_PER_ELEMENT as u32, self.config.selection_hover_color);
+        let surface = self.compositor.create_surface(qh);
+        if let (Some(manager), Some(viewporter)) = (&self.fractional_scale_manager, &self.viewporter) {
+            let viewport = viewporter.get_viewport(&surface, qh, ());
+            viewport.set_destination(width_int, height_int);
+            self.fractional_scale = Some(manager.get_fractional_scale(&surface, qh, ()));
+            self.viewport = Some(viewport);
         }
-        self.canvas.draw_box(0, 49, 1024, 1, self.config.seperator_color);
 
-        for x in &mut self.filter_results_cache {
-            x.draw(&mut self.canvas);
+        let output = self.find_output();
+        let layer = self.layer_shell.create_layer_surface(qh, surface, Layer::Top, Some("sprint-layer"), output.as_ref());
+        layer.set_keyboard_interactivity(KeyboardInteractivity::Exclusive);
+        layer.set_size(self.width, self.height);
+        layer.commit();
+        self.layer = Some(layer);
+    }
+
+    fn show(&mut self, qh: &QueueHandle<Self>) {
+        // start fresh, as if we'd just been launched
+        self.launcher.reset(&self.options.query);
+        self.first_config = true;
+        self.frame_pending = false;
+        self.create_surface(qh);
+    }
+
+    fn hide(&mut self) {
+        self.stop_key_repeat();
+        if let Some(fractional_scale) = self.fractional_scale.take() {
+            fractional_scale.destroy();
+        }
+        if let Some(viewport) = self.viewport.take() {
+            viewport.destroy();
+        }
+        // dropping the layer destroys it along with the surface
+        self.layer = None;
+        self.buffer = None;
+    }
+
+    pub fn toggle(&mut self, qh: &QueueHandle<Self>) {
+        if self.layer.is_some() {
+            self.hide();
+        } else {
+            self.show(qh);
         }
+    }
 
+    // closes sprint, or just hides it if we're running as a daemon
+    fn dismiss(&mut self) {
+        if self.options.daemon {
+            self.hide();
+        } else {
+            self.close = true;
+        }
+    }
 
-        // Push it to the surface
-        self.canvas.fill_wayland_canvas(canvas);
+    // picks the output to open on from the config, none leaves it up to the compositor
+    fn find_output(&self) -> Option<WlOutput> {
+        match self.launcher.config().output.as_str() {
+            // there's no way for us to know which output is focused, but compositors will put us
+            // on the focused one when we don't give them one
+            "focused" => None,
+            // wayland has no real concept of a primary output, so go with the one at the origin
+            "primary" => self.output_state.outputs()
+                .find(|output| self.output_state.info(output).and_then(|info| info.logical_position) == Some((0, 0)))
+                .or_else(|| self.output_state.outputs().next()),
+            name => {
+                let output = self.output_state.outputs().find(|output| {
+                    self.output_state.info(output).is_some_and(|info| info.name.as_deref() == Some(name) || info.description.as_deref() == Some(name))
+                });
+                if output.is_none() {
+                    log::warn!("Unable to find output {name}, letting the compositor choose");
+                }
+                output
+            }
+        }
+    }
 
-        self.layer.wl_surface().damage_buffer(0, 0, width_int, height_int);
-        self.layer.wl_surface().frame(qh, self.layer.wl_surface().clone());
-        buffer.attach_to(self.layer.wl_surface()).expect("Failed to attach to buffer");
-        self.layer.commit();
+    fn set_scale(&mut self, scale: f32, qh: &QueueHandle<Self>) {
+        #[allow(clippy::float_cmp)]
+        if scale == self.scale {
+            return;
+        }
+        self.scale = scale;
+        self.launcher.resize(self.width, self.height, scale);
+        self.draw_if_needed(qh);
     }
 
-    fn recreate_results_cache(&mut self) {
-        let time = Instant::now();
-        let mut transform = Vector2I::new(16, 49);
-        let standard_size = Vector2I::new(1024, HEIGHT_PER_ELEMENT);
-        self.filter_results_cache = Vec::new();
+    // loads the config again and swaps it in, keeping the old one if the new one has problems
+    fn reload_config(&mut self, qh: &QueueHandle<Self>) {
+        let Some(config_reload) = &self.options.config_reload else {
+            return;
+        };
+        log::info!("Config changed, reloading");
+        let (config, errors) = match config_reload() {
+            Ok(loaded) => loaded,
+            Err(error) => {
+                log::error!("Failed to reload config, keeping the old one: {error}");
+                self.launcher.show_error(&error);
+                self.draw_if_needed(qh);
+                return;
+            }
+        };
+        for error in &errors {
+            log::error!("{error}");
+        }
+        // a missing font has already been fallen back from, but a half applied config file could
+        // leave things looking nothing like either version of it
+        let invalid = errors.iter().any(|error| matches!(error, SprintError::ConfigRead { .. } | SprintError::InvalidConfig(_) | SprintError::InvalidEnvConfig { .. }));
+        if invalid {
+            log::warn!("Keeping the old config until the problems with the new one are fixed");
+            self.launcher.show_errors(&errors);
+            self.draw_if_needed(qh);
+            return;
+        }
 
-        for result_type in &self.config.result_order {
-            match result_type.to_lowercase().as_str() {
-                "prefixes" => {
-                    if let Some(prefix) = &self.filter_results.prefix_results {
-                        let prefix_entry = Entrybox::new(EntryBoxValue::WebPrefix(prefix.0.to_string(), prefix.1.to_string(), prefix.2.to_string()), transform, standard_size, self.config.font.clone());
-                        transform.set_y(transform.y() + HEIGHT_PER_ELEMENT);
-                        self.filter_results_cache.push(prefix_entry);
-                    }
-                },
-                "math" => {
-                    if let Some(math) = self.filter_results.math_result {
-                        let entry = Entrybox::new(EntryBoxValue::Math(math), transform, standard_size, self.config.font.clone());
-                        transform.set_y(transform.y() + HEIGHT_PER_ELEMENT);
-                        self.filter_results_cache.push(entry);
-                    }
-                },
-                "desktop" => {
-                    let mut count: u8 = 0;
-                    for desktop in &self.filter_results.desktop_results {
-                        let entry = Entrybox::new(EntryBoxValue::Desktop(desktop.to_owned()), transform, standard_size, self.config.font.clone());
-                        transform.set_y(transform.y() + HEIGHT_PER_ELEMENT);
-                        self.filter_results_cache.push(entry);
-                        count += 1;
-                        if count >= ELEMENT_LIMIT {
-                            break;
-                        }
-                    }
-                },
-                "search" => {
-                    let web_entry = Entrybox::new(EntryBoxValue::WebSearch(self.filter_results.web_result.0.to_string(), self.filter_results.web_result.1.to_string()), transform, standard_size, self.config.font.clone());
-                    transform.set_y(transform.y() + HEIGHT_PER_ELEMENT);
-                    self.filter_results_cache.push(web_entry);
-                },
-                _ => println!("Error: Unknown result type {result_type}")
+        let size_changed = (config.width, config.height) != (self.width, self.height);
+        self.width = config.width;
+        self.height = config.height;
+        self.launcher.set_config(config);
+        self.launcher.show_errors(&errors);
+        if size_changed {
+            if let Some(layer) = &self.layer {
+                layer.set_size(self.width, self.height);
+                layer.commit();
+            }
+            if let Some(viewport) = &self.viewport {
+                viewport.set_destination(i32::try_from(self.width).expect("width to i32 failed"), i32::try_from(self.height).expect("height to i32 failed"));
             }
         }
+        self.draw_if_needed(qh);
+    }
+
+    fn start_key_repeat(&mut self, raw_code: u32, key: Keysym, text: Option<String>, delay: u32, rate: NonZeroU32) {
+        let interval = Duration::from_millis(u64::from(1000 / rate.get()));
+        let timer = Timer::from_duration(Duration::from_millis(u64::from(delay)));
+        let inserted = self.loop_handle.insert_source(timer, move |_, (), state| {
+            state.key_press_handle(key, text.as_deref());
+            TimeoutAction::ToDuration(interval)
+        });
+        // not being able to repeat keys isn't worth stopping over
+        match inserted {
+            Ok(token) => self.repeat_key = Some((raw_code, token)),
+            Err(error) => log::error!("Failed to start key repeat: {}", error.error)
+        }
+    }
 
-        println!("Time to recreate results element cache: {:?}", time.elapsed());
+    fn stop_key_repeat(&mut self) {
+        if let Some((_, token)) = self.repeat_key.take() {
+            self.loop_handle.remove(token);
+        }
     }
 
     fn select(&mut self) {
-        let selected = &self.filter_results_cache[self.selected as usize];
-        selected.select();
-        self.close = true;
+        let selected = self.launcher.selected_entry();
+        match self.options.action {
+            SelectAction::Launch => {
+                // stay open so what went wrong can be seen, and something else picked
+                if let Some(Err(error)) = selected.map(Entrybox::select) {
+                    log::error!("{error}");
+                    self.launcher.show_error(&error);
+                    return;
+                }
+            },
+            SelectAction::Dmenu { print_query } => {
+                // like dmenu, fall back to what was typed if nothing matched
+                match selected.map(Entrybox::value) {
+                    Some(EntryBoxValue::Dmenu(item)) if !print_query => println!("{item}"),
+                    _ => println!("{}", self.launcher.filter())
+                }
+            },
+            SelectAction::Print => {
+                if let Some(selected) = selected {
+                    println!("{}", selected.describe().to_line());
+                }
+            },
+            SelectAction::Json => {
+                if let Some(selected) = selected {
+                    println!("{}", serde_json::to_string(&selected.describe()).expect("Failed to serialize result."));
+                }
+            }
+        }
+        // dmenu always prints something, even if it's just what was typed
+        self.made_selection = selected.is_some() || matches!(self.options.action, SelectAction::Dmenu { .. });
+        self.dismiss();
     }
 
-    fn key_press_handle(&mut self, keysym: Keysym) {
+    fn key_press_handle(&mut self, keysym: Keysym, text: Option<&str>) {
         match keysym {
             // Control characters
-            Keysym::Escape => self.close = true,
+            Keysym::Escape => self.dismiss(),
             Keysym::Return => self.select(),
-            Keysym::BackSpace => if let Some(new_filter) = self.filter_input.pop_at_cursor() { self.filter = new_filter }
+            Keysym::BackSpace => self.launcher.pop_char(),
             // Cursor movement
-            Keysym::Down => self.selected = min(u8::try_from(self.filter_results_cache.len() - 1).expect("filter results cache length to u8 failed"), self.selected + 1),
-            Keysym::Up => self.selected = if self.selected != 0 { self.selected - 1} else { 0 },
-            Keysym::Right => self.filter_input.advance_cursor(),
-            Keysym::Left => self.filter_input.reel_cursor(),
-            Keysym::Home => self.filter_input.set_cursor_to_home(),
-            Keysym::End => self.filter_input.set_cursor_to_end(),
+            Keysym::Down => self.launcher.select_next(),
+            Keysym::Up => self.launcher.select_previous(),
+            Keysym::Right => self.launcher.cursor_right(),
+            Keysym::Left => self.launcher.cursor_left(),
+            Keysym::Home => self.launcher.cursor_home(),
+            Keysym::End => self.launcher.cursor_end(),
             
             _ => {
-                if let Some(character) = keysym.key_char() {
-                    self.filter = self.filter_input.push_at_cursor(character);
+                // keys like tab and anything held with control give control characters, which
+                // don't belong in a query
+                if let Some(text) = text.filter(|text| !text.is_empty() && !text.chars().any(char::is_control)) {
+                    self.launcher.insert_text(text);
                 }
             }
         }
-        // re-do results 
-        self.filter_results.refresh_results(&self.filter, &self.config);
-        self.recreate_results_cache();
     }
 }
 
-const HEIGHT_PER_ELEMENT: i32 = 30;
-const ELEMENT_LIMIT: u8 = 50;
-
 delegate_compositor!(LayerState);
 delegate_output!(LayerState);
 delegate_shm!(LayerState);
@@ -270,94 +497,159 @@ delegate_seat!(LayerState);
 delegate_keyboard!(LayerState);
 delegate_layer!(LayerState);
 delegate_registry!(LayerState);
+delegate_noop!(LayerState: WpFractionalScaleManagerV1);
+delegate_noop!(LayerState: WpViewporter);
+delegate_noop!(LayerState: WpViewport);
+delegate_noop!(LayerState: ZwpTextInputManagerV3);
+
+// what happens when a result is picked
+#[derive(Clone, Copy, Debug)]
+pub enum SelectAction {
+    // launch the app or open the url
+    Launch,
+    // print the picked item to stdout, or what was typed if print_query is set
+    Dmenu { print_query: bool },
+    // print a description of the picked result to stdout, as a tab separated line or json
+    Print,
+    Json
+}
+
+// how sprint was launched, as opposed to the config which is how the user wants it to look and behave
+pub struct LaunchOptions {
+    // daemons hide instead of closing, and wait to be toggled back on
+    pub daemon: bool,
+    // what the search box starts with
+    pub query: String,
+    pub action: SelectAction,
+    // the items to pick from in dmenu mode
+    pub dmenu_items: Vec<String>,
+    // anything that went wrong starting up, shown once we're open so it isn't missed
+    pub errors: Vec<SprintError>,
+    // how to load the config again when its files change, none to never reload it
+    pub config_reload: Option<ConfigReload>
+}
 
-pub fn create_layer(config: SprintConfig) {
-    let conn = Connection::connect_to_env().expect("Unable to connect to a compositor.");
-    let (globals, mut event_queue) = registry_queue_init(&conn).unwrap();
+// loads the config again the same way it was at launch, for when its files change
+pub type ConfigReload = Box<dyn Fn() -> Result<(SprintConfig, Vec<SprintError>)>>;
+
+// returns true if a result was picked, and false if sprint was dismissed
+pub fn create_layer(config: SprintConfig, mut options: LaunchOptions) -> Result<bool> {
+    let conn = Connection::connect_to_env()?;
+    let (globals, mut event_queue) = registry_queue_init(&conn)?;
     let qh = event_queue.handle();
+    let mut event_loop: EventLoop<LayerState> = EventLoop::try_new()?;
 
-    let compositor = CompositorState::bind(&globals, &qh).expect("Compositor does not support 'wl_compositor'");
-    let layer_shell = LayerShell::bind(&globals, &qh).expect("Compositor does not support 'zwlr_layer_shell_v1'");
+    let compositor = CompositorState::bind(&globals, &qh).map_err(|source| SprintError::Unsupported { interface: "wl_compositor", source })?;
+    let layer_shell = LayerShell::bind(&globals, &qh).map_err(|source| SprintError::Unsupported { interface: "zwlr_layer_shell_v1", source })?;
     // software rendering because im too lazy to use wgpu
-    let shm = Shm::bind(&globals, &qh).expect("Compositor does not support `wl_shm`");
-
-    // create our surface and layer
-    let width: u32 = 1024;
-    let height: u32 = 512;
-    let surface = compositor.create_surface(&qh);
-    let layer = layer_shell.create_layer_surface(&qh, surface, Layer::Top, Some("sprint-layer"), None);
-    layer.set_keyboard_interactivity(KeyboardInteractivity::Exclusive);
-    layer.set_size(width, height);
-    layer.commit();
-    let pool = SlotPool::new((width * height * 4) as usize, &shm).expect("Failed to create pool");
+    let shm = Shm::bind(&globals, &qh).map_err(|source| SprintError::Unsupported { interface: "wl_shm", source })?;
+    // both of these are optional, without them we fall back to integer scaling through wl_surface
+    let fractional_scale_manager = globals.bind::<WpFractionalScaleManagerV1, _, _>(&qh, 1..=1, ()).ok();
+    let viewporter = globals.bind::<WpViewporter, _, _>(&qh, 1..=1, ()).ok();
+    // without this only what the keyboard types directly can be typed
+    let text_input_manager = globals.bind::<ZwpTextInputManagerV3, _, _>(&qh, 1..=1, ()).ok();
+
+    let width = config.width;
+    let height = config.height;
+    let pool = SlotPool::new((width * height * 4) as usize, &shm)?;
+
+    let mut filter_results = SprintResults::new();
+    filter_results.set_dmenu_items(std::mem::take(&mut options.dmenu_items));
 
     // state
     let mut state = LayerState {
         registry_state: RegistryState::new(&globals),
         seat_state: SeatState::new(&globals, &qh),
         output_state: OutputState::new(&globals, &qh),
+        compositor,
+        layer_shell,
+        fractional_scale_manager,
+        viewporter,
+        text_input_manager,
         shm,
         close: false,
+        made_selection: false,
         first_config: true,
         pool,
-        layer,
+        frame_pending: false,
+        buffer: None,
+        layer: None,
         keyboard: None,
-        canvas: RenderCanvas::new(width, height),
+        text_input: None,
         width,
         height,
         repeat_key: None,
         repeat_delay: None,
         repeat_rate: None,
+        loop_handle: event_loop.handle(),
+        scale: 1.0,
+        fractional_scale: None,
+        viewport: None,
 
-        filter: String::new(),
-        filter_results: SprintResults::new(),
-        selected: 0,
-
-        filter_input: InputBox::new("", "Search...", Vector2I::new(16, 8), Vector2I::new(996, 32), &config.font),
-        filter_results_cache: Vec::new(),
-        no_results_label: TextLabel::new("¯\\_(._.)_/¯", config.font.clone(), 18.0, Vector2I::new(462, 240), Vector2I::new(100, 32)),
-        config
+        launcher: Launcher::new(config, &options.query, filter_results),
+        options
     };
-    state.filter_results.refresh_results("", &state.config);
-    state.canvas.wipe(Color::new(25, 25, 25, 255));
-    state.recreate_results_cache();
-
-    // event loop
-    loop {
-        // key repetition
-        // this var holds the key to repeat, just gets around the double mut borrow problem
-        let mut key_repeat = None;
-        // if we're holding down a key...
-        if let Some(RepeatKeyInfo(key, ref mut time, ref mut active)) = state.repeat_key {
-            let repeat_delay = state.repeat_delay.expect("repeat delay is set to none");
-            let repeat_rate = state.repeat_rate.expect("repeat rate is set to none");
-            // is the delay past yet?
-            if !*active && time.elapsed().as_millis() > repeat_delay.into() {
-                // if so, press the key and setup the repeatition
-                *active = true;
-                *time = Instant::now();
-                key_repeat = Some(key);
+    let errors = std::mem::take(&mut state.options.errors);
+    state.launcher.show_errors(&errors);
+
+    // wait for the outputs to be announced so we can pick one, then create our surface and layer
+    event_queue.roundtrip(&mut state)?;
+    if state.options.daemon {
+        // stay hidden until we're toggled on
+        let listener = daemon::bind_listener().map_err(SprintError::DaemonSocket)?;
+        let qh = qh.clone();
+        event_loop.handle().insert_source(Generic::new(listener, Interest::READ, Mode::Level), move |_, listener, state| {
+            // a misbehaving client shouldn't take the daemon down with it
+            match listener.accept().and_then(|(stream, _)| daemon::read_command(stream)) {
+                Ok(command) if command == daemon::TOGGLE_COMMAND => state.toggle(&qh),
+                Ok(command) => log::warn!("Unknown daemon command {command}"),
+                Err(error) => log::error!("Failed to read daemon command: {error}")
             }
+            Ok(PostAction::Continue)
+        }).map_err(|error| error.error)?;
+    } else {
+        state.create_surface(&qh);
+    }
 
-            // the actual repeptition once its active
-            if *active {
-                let char_rate = 1000 / repeat_rate;
-                if time.elapsed().as_millis() > char_rate.into() {
-                    *time = Instant::now();
-                    key_repeat = Some(key);
-                }
-            }
-        }
-        // press it if needed
-        if let Some(repeat) = key_repeat {
-            state.key_press_handle(repeat);
+    if state.options.config_reload.is_some() {
+        // without inotify the config just won't reload, which isn't worth failing over
+        match ConfigWatcher::new(&state.launcher.config().watch_paths) {
+            Ok(watcher) => {
+                let qh = qh.clone();
+                event_loop.handle().insert_source(Generic::new(watcher, Interest::READ, Mode::Level), move |_, watcher, state| {
+                    // SAFETY: the watcher isn't dropped while it's registered with the event loop
+                    let watcher = unsafe { watcher.get_mut() };
+                    match watcher.changed() {
+                        Ok(true) => {
+                            state.reload_config(&qh);
+                            // the new config may include different files to the old one
+                            if let Err(error) = watcher.watch(&state.launcher.config().watch_paths) {
+                                log::error!("Unable to watch the config for changes: {error}");
+                            }
+                        },
+                        Ok(false) => {},
+                        Err(error) => log::error!("Failed to read config changes: {error}")
+                    }
+                    Ok(PostAction::Continue)
+                }).map_err(|error| error.error)?;
+            },
+            Err(error) => log::warn!("Unable to watch the config for changes: {error}")
         }
+    }
+
+    // from here on wayland events are dispatched through the event loop, alongside any timers
+    WaylandSource::new(conn, event_queue).insert(event_loop.handle()).map_err(|error| error.error)?;
+
+    // event loop
+    loop {
+        // draw anything that changed since we last blocked, be it from events or timers
+        state.draw_if_needed(&qh);
 
-        // now back to boring wayland handling
-        event_queue.blocking_dispatch(&mut state).unwrap();
+        event_loop.dispatch(None, &mut state)?;
 
         if state.close {
             break;
         }
     }
+    Ok(state.made_selection)
 }
@@ -0,0 +1,231 @@
+// renders the launcher offscreen and compares it against the golden images in tests/snapshots,
+// run with UPDATE_SNAPSHOTS=1 to write new ones after an intended change to how things look
+use std::{env, fs, path::PathBuf, sync::Arc};
+
+use font_kit::font::Font;
+use sprint::{error::SprintError, launcher::Launcher, render_canvas::Color, results::SprintResults, sprint_config::SprintConfig};
+
+// bundled so the snapshots don't depend on what fonts the machine running them has
+const TEST_FONT: &[u8] = include_bytes!("fonts/DejaVuSans.ttf");
+
+fn default_config() -> SprintConfig {
+    let font = Font::from_bytes(Arc::new(TEST_FONT.to_vec()), 0).expect("Failed to load test font.");
+    SprintConfig::with_font(font)
+}
+
+fn themed_config() -> SprintConfig {
+    let mut config = default_config();
+    config.background_color = Color::new(238, 232, 213, 255);
+    config.foreground_color = Color::new(253, 246, 227, 255);
+    config.seperator_color = Color::new(38, 139, 210, 255);
+    config.selection_hover_color = Color::new(147, 161, 161, 255);
+    config.placeholder = "Run something...".to_string();
+    config
+}
+
+// only dmenu items, so nothing depends on the desktop files installed where the tests run
+fn dmenu_config(mut config: SprintConfig) -> SprintConfig {
+    config.search.result_order = vec!["dmenu".to_string()];
+    config
+}
+
+// no desktop files are scanned, so results are the same on every machine
+fn results() -> SprintResults {
+    let mut results = SprintResults::default();
+    results.set_dmenu_items(["Firefox", "Files", "Terminal", "Text Editor", "System Monitor"].map(String::from).to_vec());
+    results
+}
+
+fn assert_snapshot(name: &str, launcher: &mut Launcher) {
+    launcher.draw();
+    let mut png = Vec::new();
+    launcher.canvas().write_png(&mut png).expect("Failed to encode snapshot.");
+
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/snapshots").join(format!("{name}.png"));
+    if env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        fs::write(&path, &png).expect("Failed to write snapshot.");
+        return;
+    }
+
+    let expected = fs::read(&path).unwrap_or_else(|_| panic!("Missing snapshot {}, run with UPDATE_SNAPSHOTS=1 to create it", path.display()));
+    // compare pixels rather than files, so a different png encoder doesn't fail anything
+    if let Err(difference) = compare(&decode(&expected), &decode(&png)) {
+        let actual_path = path.with_extension("actual.png");
+        fs::write(&actual_path, &png).expect("Failed to write actual snapshot.");
+        panic!("Snapshot {name} doesn't match, {difference}, see {} for what was rendered", actual_path.display());
+    }
+}
+
+// how far apart a channel can be before the pixel counts as different, and how many pixels in a
+// thousand can differ. glyphs come from the system's freetype, whose antialiasing can change a
+// little between versions, but anything laid out differently changes far more than this
+const CHANNEL_TOLERANCE: u8 = 48;
+const DIFFERING_PER_THOUSAND: usize = 1;
+
+fn compare(expected: &(u32, u32, Vec<u8>), actual: &(u32, u32, Vec<u8>)) -> Result<(), String> {
+    let ((expected_width, expected_height, expected_pixels), (actual_width, actual_height, actual_pixels)) = (expected, actual);
+    if (expected_width, expected_height) != (actual_width, actual_height) || expected_pixels.len() != actual_pixels.len() {
+        return Err(format!("it's {actual_width}x{actual_height} rather than {expected_width}x{expected_height}"));
+    }
+    let pixel_count = (*expected_width * *expected_height) as usize;
+    let channels = expected_pixels.len() / pixel_count;
+    let differing = expected_pixels.chunks(channels).zip(actual_pixels.chunks(channels))
+        .filter(|(expected, actual)| expected.iter().zip(actual.iter()).any(|(expected, actual)| expected.abs_diff(*actual) > CHANNEL_TOLERANCE))
+        .count();
+    let allowed = pixel_count * DIFFERING_PER_THOUSAND / 1000;
+    if differing > allowed {
+        return Err(format!("{differing} pixels differ where at most {allowed} can"));
+    }
+    Ok(())
+}
+
+fn decode(png: &[u8]) -> (u32, u32, Vec<u8>) {
+    let mut reader = png::Decoder::new(std::io::Cursor::new(png)).read_info().expect("Failed to read snapshot.");
+    let mut pixels = vec![0; reader.output_buffer_size().expect("Snapshot too big.")];
+    let info = reader.next_frame(&mut pixels).expect("Failed to decode snapshot.");
+    pixels.truncate(info.buffer_size());
+    (info.width, info.height, pixels)
+}
+
+#[test]
+fn default_empty() {
+    let mut launcher = Launcher::new(default_config(), "", results());
+    assert_snapshot("default_empty", &mut launcher);
+}
+
+#[test]
+fn default_math() {
+    let mut launcher = Launcher::new(default_config(), "12*3", results());
+    assert_snapshot("default_math", &mut launcher);
+}
+
+#[test]
+fn default_selection() {
+    let mut launcher = Launcher::new(dmenu_config(default_config()), "", results());
+    launcher.select_next();
+    launcher.select_next();
+    assert_snapshot("default_selection", &mut launcher);
+}
+
+#[test]
+fn default_no_results() {
+    let mut launcher = Launcher::new(dmenu_config(default_config()), "zzz", results());
+    assert_snapshot("default_no_results", &mut launcher);
+}
+
+#[test]
+fn themed_list() {
+    let mut launcher = Launcher::new(dmenu_config(themed_config()), "", results());
+    launcher.push_char('t');
+    launcher.select_next();
+    assert_snapshot("themed_list", &mut launcher);
+}
+
+#[test]
+fn themed_placeholder() {
+    let mut launcher = Launcher::new(themed_config(), "", results());
+    assert_snapshot("themed_placeholder", &mut launcher);
+}
+
+#[test]
+fn themed_scaled() {
+    let mut config = dmenu_config(themed_config());
+    config.width = 480;
+    config.height = 240;
+    let mut launcher = Launcher::new(config, "fi", results());
+    launcher.resize(480, 240, 1.5);
+    assert_snapshot("themed_scaled", &mut launcher);
+}
+
+#[test]
+fn themed_font_sizes() {
+    let mut config = dmenu_config(themed_config());
+    config.input_font_size = 22.0;
+    config.result_font_size = 12.0;
+    let mut launcher = Launcher::new(config, "e", results());
+    assert_snapshot("themed_font_sizes", &mut launcher);
+}
+
+fn narrow_config() -> SprintConfig {
+    let mut config = dmenu_config(default_config());
+    config.width = 320;
+    config.height = 160;
+    config
+}
+
+// long items are cut short with an ellipsis, at the end for names and in the middle for paths
+#[test]
+fn narrow_long_results() {
+    let mut results = SprintResults::default();
+    results.set_dmenu_items(vec![
+        "A rather long application name that won't fit".to_string(),
+        "/usr/share/applications/org.example.LongName.desktop".to_string()
+    ]);
+    let mut launcher = Launcher::new(narrow_config(), "", results);
+    assert_snapshot("narrow_long_results", &mut launcher);
+}
+
+// a long query scrolls to keep the cursor in view
+#[test]
+fn narrow_long_query() {
+    let mut launcher = Launcher::new(narrow_config(), "", results());
+    for character in "a query far too long to fit in the box".chars() {
+        launcher.push_char(character);
+    }
+    assert_snapshot("narrow_long_query", &mut launcher);
+}
+
+// right to left text is drawn in the order it's read, and the cursor moves through it on screen
+#[test]
+fn default_bidi() {
+    let mut results = SprintResults::default();
+    results.set_dmenu_items(vec![
+        "שלום world".to_string(),
+        "Notes (שלום עולם)".to_string(),
+        "مرحبا بالعالم 123".to_string()
+    ]);
+    let mut launcher = Launcher::new(dmenu_config(default_config()), "", results);
+    for character in "שלוםx".chars() {
+        launcher.push_char(character);
+    }
+    launcher.pop_char();
+    // the cursor starts on the left, after the last letter read
+    launcher.cursor_right();
+    assert_snapshot("default_bidi", &mut launcher);
+}
+
+// text still being composed by an input method is underlined, and doesn't filter the results yet
+#[test]
+fn default_preedit() {
+    let mut launcher = Launcher::new(dmenu_config(default_config()), "", results());
+    launcher.insert_text("Te");
+    launcher.set_preedit("xt Ed", Some(2));
+    assert_snapshot("default_preedit", &mut launcher);
+}
+
+#[test]
+fn default_error_banner() {
+    let mut launcher = Launcher::new(dmenu_config(default_config()), "", results());
+    launcher.show_error(&SprintError::MissingExec { name: "Firefox".to_string() });
+    assert_snapshot("default_error_banner", &mut launcher);
+}
+
+// drawing only what changed has to end up the same as drawing everything from scratch
+#[test]
+fn partial_redraw_matches_full() {
+    let mut partial = Launcher::new(dmenu_config(default_config()), "", results());
+    partial.draw();
+    partial.select_next();
+    partial.draw();
+    partial.push_char('e');
+    partial.draw();
+    partial.cursor_home();
+    partial.draw();
+
+    let mut full = Launcher::new(dmenu_config(default_config()), "e", results());
+    full.select_next();
+    full.cursor_home();
+    full.draw();
+
+    assert!(partial.canvas().to_rgba() == full.canvas().to_rgba(), "partial redraw differs from a full redraw");
+}